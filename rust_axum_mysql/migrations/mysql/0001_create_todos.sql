@@ -0,0 +1,6 @@
+CREATE TABLE todos (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    title VARCHAR(200) NOT NULL,
+    -- INT rather than BOOLEAN: MySQL's BOOLEAN is a TINYINT, which sqlx's Any driver can't decode.
+    completed INT NOT NULL DEFAULT 0
+);
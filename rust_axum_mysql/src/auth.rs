@@ -0,0 +1,360 @@
+use crate::config::Config;
+use crate::problem::Problem;
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts, Request, State},
+    http::{header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, errors::ErrorKind};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "user" => Some(Role::User),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/**
+    Who sent the request, as vouched for by their token.
+
+    The `authenticate` middleware puts it in the request's extensions, and
+    this extractor takes it out again, so a handler that needs a user just
+    asks for one:
+
+        async fn create_todo(user: CurrentUser, ...) -> ...
+
+    and gets a 401 instead when the request carried no token.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct CurrentUser {
+    pub id: u64,
+    pub username: String,
+    pub role: Role,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = Problem;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<CurrentUser, Problem> {
+        parts
+            .extensions
+            .get::<CurrentUser>()
+            .cloned()
+            .ok_or_else(|| Problem::unauthorized("This needs a bearer token in the Authorization header"))
+    }
+}
+
+/// `Option<CurrentUser>`, for a handler that has another way to find out who's asking.
+impl<S: Send + Sync> OptionalFromRequestParts<S> for CurrentUser {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Option<CurrentUser>, Infallible> {
+        Ok(parts.extensions.get::<CurrentUser>().cloned())
+    }
+}
+
+/**
+    What goes inside a token.
+
+    What is a JWT?
+        Three base64 parts separated by dots: a header naming the algorithm,
+        these claims as JSON, and a signature over both. Anyone can read the
+        claims, but only the holder of the key can produce a signature that
+        verifies, so the server can trust them without a database lookup.
+
+    `sub`, `iat` and `exp` are the registered claim names for the subject,
+    when the token was issued and when it stops being valid.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    name: String,
+    role: Role,
+    iat: u64,
+    exp: u64,
+}
+
+/**
+    The keys tokens are signed and checked with.
+
+    HS256 or RS256?
+        HS256 signs with a shared secret: whoever can check a token can also
+        make one. That's fine while this server is the only one checking.
+        RS256 signs with an RSA private key and checks with the public key,
+        so other services can be given the public key to verify tokens
+        without being able to issue them.
+
+    Only the configured algorithm is accepted when checking. Trusting the
+    `alg` in the token's own header is the classic JWT mistake: an attacker
+    could pick "none", or make an HS256 token "signed" with the public key.
+*/
+pub struct Keys {
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    ttl: Duration,
+}
+
+impl Keys {
+    pub fn hs256(secret: &[u8], ttl: Duration) -> Keys {
+        Keys {
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            ttl,
+        }
+    }
+
+    pub fn rs256(private_pem: &[u8], public_pem: &[u8], ttl: Duration) -> Result<Keys, String> {
+        Ok(Keys {
+            algorithm: Algorithm::RS256,
+            encoding: EncodingKey::from_rsa_pem(private_pem).map_err(|e| format!("private key: {}", e))?,
+            decoding: DecodingKey::from_rsa_pem(public_pem).map_err(|e| format!("public key: {}", e))?,
+            ttl,
+        })
+    }
+
+    /// The keys the configuration asks for. Config::validate has already checked the right settings are there.
+    pub fn from_config(config: &Config) -> Result<Keys, String> {
+        if config.jwt_algorithm == "RS256" {
+            let read = |path: &Option<std::path::PathBuf>| {
+                let path = path.as_ref().expect("validated");
+                std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+            };
+            return Keys::rs256(&read(&config.jwt_private_key)?, &read(&config.jwt_public_key)?, config.token_ttl);
+        }
+
+        match &config.jwt_secret {
+            Some(secret) => Ok(Keys::hs256(secret.as_bytes(), config.token_ttl)),
+            None => {
+                tracing::warn!("No JWT secret configured, using a random one: tokens won't survive a restart");
+                let mut secret = [0u8; 32];
+                OsRng.fill_bytes(&mut secret);
+                Ok(Keys::hs256(&secret, config.token_ttl))
+            }
+        }
+    }
+
+    /// A signed token for `user`, and how many seconds it's valid for.
+    pub fn issue(&self, user: &CurrentUser) -> Result<(String, u64), jsonwebtoken::errors::Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let claims = Claims {
+            sub: user.id.to_string(),
+            name: user.username.clone(),
+            role: user.role,
+            iat: now,
+            exp: now.saturating_add(self.ttl.as_secs()),
+        };
+        let token = jsonwebtoken::encode(&Header::new(self.algorithm), &claims, &self.encoding)?;
+        Ok((token, self.ttl.as_secs()))
+    }
+
+    /**
+        The user a token was issued to, if it's genuine and still valid.
+
+        The messages tell an expired token apart from a bad one, since a
+        client should log in again for the first but has a bug for the
+        second. They don't say more than that about what was wrong.
+    */
+    pub fn verify(&self, token: &str) -> Result<CurrentUser, Problem> {
+        let mut validation = Validation::new(self.algorithm);
+        // No grace period after `exp`: expired means expired.
+        validation.leeway = 0;
+
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.decoding, &validation)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => Problem::unauthorized("The token has expired, log in again"),
+                _ => Problem::unauthorized("The token is not valid"),
+            })?
+            .claims;
+        let id = claims.sub.parse().map_err(|_| Problem::unauthorized("The token is not valid"))?;
+        Ok(CurrentUser { id, username: claims.name, role: claims.role })
+    }
+}
+
+/**
+    Checks the bearer token, if there is one, and makes its user the
+    request's CurrentUser.
+
+    A request without an Authorization header goes through untouched:
+    whether a route needs a user is up to the route (see `require_user`).
+    A header that's there but wrong is rejected right away, though, since
+    quietly treating a client with a bad token as anonymous would only hide
+    its bug.
+*/
+pub async fn authenticate(State(keys): State<Arc<Keys>>, mut req: Request, next: Next) -> Result<Response, Problem> {
+    if let Some(value) = req.headers().get(header::AUTHORIZATION) {
+        let token = value
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Problem::unauthorized("The Authorization header must be \"Bearer <token>\""))?;
+        let user = keys.verify(token.trim())?;
+        req.extensions_mut().insert(user);
+    }
+    Ok(next.run(req).await)
+}
+
+/*
+    Guards for `route_layer`, in the same middleware::from_fn style:
+
+        .route("/todos/{id}", delete(delete_todo))
+        .route_layer(middleware::from_fn(auth::require_admin))
+
+    Taking a CurrentUser is what does the work: the extractor rejects the
+    request with a 401 before the guard's body even runs.
+*/
+
+/// Lets through any logged-in user.
+pub async fn require_user(_user: CurrentUser, req: Request, next: Next) -> Response {
+    next.run(req).await
+}
+
+/// Lets through admins only; anyone else logged in gets a 403.
+pub async fn require_admin(user: CurrentUser, req: Request, next: Next) -> Result<Response, Problem> {
+    if user.role != Role::Admin {
+        return Err(Problem::forbidden("Only admins can do this"));
+    }
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppState, app};
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Method, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    const PRIVATE_PEM: &[u8] = include_bytes!("../testdata/jwt_rs256_private.pem");
+    const PUBLIC_PEM: &[u8] = include_bytes!("../testdata/jwt_rs256_public.pem");
+
+    fn alice(role: Role) -> CurrentUser {
+        CurrentUser { id: 7, username: "alice".to_string(), role }
+    }
+
+    /// A token that expired a minute ago, signed with the right key.
+    fn expired_token(keys: &Keys) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let claims =
+            Claims { sub: "7".to_string(), name: "alice".to_string(), role: Role::User, iat: now - 120, exp: now - 60 };
+        jsonwebtoken::encode(&Header::new(keys.algorithm), &claims, &keys.encoding).unwrap()
+    }
+
+    /// The same token with its claims swapped for ones that say "admin", keeping the original signature.
+    fn tampered(token: &str, keys: &Keys) -> String {
+        let (admin, _) = keys.issue(&alice(Role::Admin)).unwrap();
+        let parts: Vec<&str> = token.split('.').collect();
+        let admin_claims = admin.split('.').nth(1).unwrap();
+        format!("{}.{}.{}", parts[0], admin_claims, parts[2])
+    }
+
+    #[test]
+    fn tokens_round_trip_with_either_algorithm() {
+        let keys = [
+            Keys::hs256(b"a secret that is long enough for hs256", Duration::from_secs(60)),
+            Keys::rs256(PRIVATE_PEM, PUBLIC_PEM, Duration::from_secs(60)).unwrap(),
+        ];
+        for keys in keys {
+            let (token, expires_in) = keys.issue(&alice(Role::User)).unwrap();
+            assert_eq!(expires_in, 60);
+            assert_eq!(keys.verify(&token).unwrap(), alice(Role::User));
+
+            assert!(keys.verify(&expired_token(&keys)).unwrap_err().detail().contains("expired"));
+            assert!(keys.verify(&tampered(&token, &keys)).unwrap_err().detail().contains("not valid"));
+        }
+    }
+
+    #[test]
+    fn other_algorithms_and_keys_are_refused() {
+        let hs256 = Keys::hs256(b"a secret that is long enough for hs256", Duration::from_secs(60));
+        let other = Keys::hs256(b"some other secret, also long enough", Duration::from_secs(60));
+        let rs256 = Keys::rs256(PRIVATE_PEM, PUBLIC_PEM, Duration::from_secs(60)).unwrap();
+
+        let (token, _) = other.issue(&alice(Role::User)).unwrap();
+        assert!(hs256.verify(&token).is_err());
+        let (token, _) = rs256.issue(&alice(Role::User)).unwrap();
+        assert!(hs256.verify(&token).is_err());
+
+        // An HS256 token "signed" with the public key must not pass for an RS256 one.
+        let forged = Keys::hs256(PUBLIC_PEM, Duration::from_secs(60));
+        let (token, _) = forged.issue(&alice(Role::Admin)).unwrap();
+        assert!(rs256.verify(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn routes_check_tokens_and_roles() {
+        let state = AppState::for_tests().await;
+        let keys = state.keys.clone();
+        let app = app(state);
+        let delete = |token: Option<String>| {
+            let mut request = Request::builder().method(Method::DELETE).uri("/todos/1");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        let send = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let authenticate = response.headers().get(header::WWW_AUTHENTICATE).cloned();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let problem: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+                (status, authenticate, problem["detail"].as_str().unwrap_or_default().to_string())
+            }
+        };
+
+        let (status, authenticate, detail) = send(delete(None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(authenticate.unwrap(), "Bearer");
+        assert!(detail.contains("needs a bearer token"), "{}", detail);
+
+        let (status, _, detail) = send(delete(Some(expired_token(&keys)))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(detail.contains("expired"), "{}", detail);
+
+        let (user_token, _) = keys.issue(&alice(Role::User)).unwrap();
+        let (status, _, _) = send(delete(Some(tampered(&user_token, &keys)))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _, _) = send(delete(Some(user_token))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // An admin gets as far as the handler, which finds nothing to delete.
+        let (admin_token, _) = keys.issue(&alice(Role::Admin)).unwrap();
+        let (status, _, _) = send(delete(Some(admin_token))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Reading needs no token at all.
+        let response = app.oneshot(Request::get("/todos").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
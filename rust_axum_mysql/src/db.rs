@@ -0,0 +1,100 @@
+use sqlx::{
+    AnyPool, Connection,
+    any::AnyPoolOptions,
+    migrate::Migrator,
+};
+use std::time::Duration;
+
+/// Used when no database URL is configured, so the server runs without a MySQL server around.
+pub const FALLBACK_URL: &str = "sqlite://todos.db?mode=rwc";
+
+/**
+    The schema, one migrations folder per database.
+
+    Why two folders?
+        MySQL and SQLite disagree on the DDL (AUTO_INCREMENT vs AUTOINCREMENT,
+        VARCHAR vs TEXT), so one set of CREATE TABLE statements can't serve
+        both. The queries in store.rs only use SQL both understand, so that
+        is the only place the two differ.
+
+    sqlx::migrate! embeds the .sql files into the binary at compile time, and
+    Migrator::run records what it applied in a `_sqlx_migrations` table, so
+    running it on every start only applies what's new.
+*/
+static MYSQL_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/mysql");
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/**
+    A connection pool to MySQL or SQLite, whichever the URL names, with the
+    migrations applied.
+
+    What is AnyPool?
+        sqlx has a pool type per database (MySqlPool, SqlitePool). AnyPool
+        picks the driver at runtime from the URL scheme, so the same binary
+        talks to MySQL in production and to SQLite in tests, at the cost of
+        only supporting the types every driver has (no BOOLEAN on MySQL, for
+        one).
+
+    Why a pool?
+        Opening a connection is a network round trip plus authentication.
+        The pool keeps a few open and lends one to each query, and a request
+        waits at most `acquire_timeout` for one to become free instead of
+        hanging when the database is overloaded.
+*/
+pub async fn connect(url: &str, acquire_timeout: Duration) -> Result<AnyPool, sqlx::Error> {
+    sqlx::any::install_default_drivers();
+
+    let mut options = AnyPoolOptions::new().max_connections(10).acquire_timeout(acquire_timeout);
+    if url.contains(":memory:") || url.contains("mode=memory") {
+        // Every connection to an in-memory SQLite database gets a database of its own,
+        // so keep exactly one and never let it be closed.
+        options = options.max_connections(1).min_connections(1).idle_timeout(None).max_lifetime(None);
+    }
+    let pool = options.connect(url).await?;
+
+    let migrator = if url.starts_with("mysql:") { &MYSQL_MIGRATIONS } else { &SQLITE_MIGRATIONS };
+    migrator.run(&pool).await?;
+    Ok(pool)
+}
+
+/// Whether the database answers, within a second.
+pub async fn ping(pool: &AnyPool) -> bool {
+    let ping = async {
+        let mut connection = pool.acquire().await?;
+        connection.ping().await
+    };
+    matches!(tokio::time::timeout(Duration::from_secs(1), ping).await, Ok(Ok(())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn migrations_run_once() {
+        let pool = connect("sqlite::memory:", Duration::from_secs(1)).await.unwrap();
+        assert!(ping(&pool).await);
+
+        // Running them again is a no-op rather than a "table already exists" error.
+        SQLITE_MIGRATIONS.run(&pool).await.unwrap();
+        let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations").fetch_one(&pool).await.unwrap();
+        assert_eq!(applied, SQLITE_MIGRATIONS.iter().count() as i64);
+    }
+
+    #[tokio::test]
+    async fn health_reports_the_database() {
+        use crate::{AppState, app};
+        use axum::{body::Body, http::{Request, StatusCode}};
+        use tower::ServiceExt;
+
+        let state = AppState::for_tests().await;
+        let pool = state.db.clone();
+        let app = app(state);
+        let health = || Request::get("/health").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(health()).await.unwrap().status(), StatusCode::OK);
+
+        pool.close().await;
+        assert!(!ping(&pool).await);
+        assert_eq!(app.oneshot(health()).await.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::auth::{CurrentUser, Keys};
+use crate::extract::AppQuery;
+use crate::problem::{PROBLEM, Problem};
+use crate::todos::Todo;
+use axum::{
+    extract::{
+        FromRef, State,
+        ws::{Message, WebSocket, WebSocketUpgrade, rejection::WebSocketUpgradeRejection},
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+/// Events a subscriber can fall behind by before it starts missing them, unless set with Events::with.
+const CAPACITY: usize = 256;
+/// How often a connection is pinged, unless set with Events::with. One that hasn't answered by the next
+/// ping is closed.
+const HEARTBEAT: Duration = Duration::from_secs(30);
+/// Longest a single message may take to go out before the client counts as gone.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+    GET /ws, a WebSocket of live updates:
+
+        const ws = new WebSocket(`ws://localhost:3001/ws?access_token=${token}`);
+        ws.onmessage = (message) => console.log(JSON.parse(message.data));
+        // {"type": "todo_created", "todo": {"id": 1, "title": "Buy milk", "completed": false}}
+
+    Messages only go from the server to the client. Anything the client
+    sends is ignored, apart from counting as a sign of life.
+*/
+pub fn router<S>() -> OpenApiRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Events: FromRef<S>,
+    Arc<Keys>: FromRef<S>,
+{
+    OpenApiRouter::new().routes(routes!(subscribe))
+}
+
+/// What a subscriber is sent, one JSON object per message, told apart by `type`.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    TodoCreated { todo: Todo },
+    TodoUpdated { todo: Todo },
+    TodoDeleted { id: u64 },
+    /// Events were published faster than this connection took them and `missed` were dropped. Fetch what
+    /// you need again (GET /todos) rather than trusting the picture built from events so far.
+    Lagged { missed: u64 },
+}
+
+/**
+    The channel handlers publish to and every /ws connection listens on.
+
+    What is a broadcast channel?
+        Every message sent goes to every receiver, each of which reads at
+        its own pace. The channel keeps the last `capacity` messages; a
+        receiver that falls further behind than that doesn't hold anyone
+        else up, it's told how many it missed (RecvError::Lagged) and
+        carries on from the oldest one still kept.
+
+    Why does that matter here?
+        It's the first half of the backpressure: publishing never waits, so
+        a slow WebSocket client can't slow down the request that created a
+        todo. The second half is in `stream`: a client that stops reading
+        altogether is disconnected instead of being buffered forever.
+*/
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+    heartbeat: Duration,
+}
+
+impl Events {
+    pub fn new() -> Events {
+        Events::with(CAPACITY, HEARTBEAT)
+    }
+
+    /// Keeps `capacity` events for subscribers that fall behind, and pings each connection every `heartbeat`.
+    pub fn with(capacity: usize, heartbeat: Duration) -> Events {
+        Events { sender: broadcast::channel(capacity).0, heartbeat }
+    }
+
+    pub fn publish(&self, event: Event) {
+        // An error only means nobody is subscribed right now, which is fine.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for Events {
+    fn default() -> Events {
+        Events::new()
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Subscribe {
+    /// The bearer token from POST /auth/login, for clients that can't send an Authorization header.
+    access_token: Option<String>,
+}
+
+/**
+    Checks who's asking, then upgrades the connection.
+
+    Why a token in the query string?
+        A browser's `new WebSocket(url)` can't set an Authorization header,
+        so the token may come as ?access_token= instead. The access log
+        only records the path, so it doesn't end up there. Clients that
+        can set headers should, as for any other route.
+
+    Subscribing happens before the upgrade, so an event published while
+    the handshake is still going on isn't lost.
+*/
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "events",
+    summary = "Subscribe to live updates",
+    description = "Upgrades to a WebSocket that sends an Event as JSON whenever a todo is created, \
+                   changed or deleted. Needs a logged-in user, with a bearer token in the Authorization \
+                   header or the access_token parameter.",
+    params(Subscribe),
+    security(("bearer" = [])),
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 400, description = "Not a WebSocket handshake", body = Problem, content_type = PROBLEM),
+        (status = 401, description = "No token, or not a valid one", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn subscribe(
+    State(events): State<Events>,
+    State(keys): State<Arc<Keys>>,
+    user: Option<CurrentUser>,
+    AppQuery(query): AppQuery<Subscribe>,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Result<Response, Problem> {
+    let user = match (user, query.access_token) {
+        (Some(user), _) => user,
+        (None, Some(token)) => keys.verify(&token)?,
+        (None, None) => return Err(Problem::unauthorized("This needs a bearer token, in a header or ?access_token=")),
+    };
+    let upgrade = upgrade?;
+    let receiver = events.subscribe();
+    Ok(upgrade.on_upgrade(move |socket| stream(socket, receiver, events.heartbeat, user)))
+}
+
+/**
+    Sends the events to one client until either side is done.
+
+    Why ping?
+        A client that vanished without closing (a laptop lid shut, a phone
+        out of range) leaves a connection that looks open forever, and a
+        proxy may drop one that's been quiet too long. Pinging keeps it
+        busy, and a client that hasn't answered (or sent anything else) by
+        the next ping is taken to be gone.
+*/
+async fn stream(mut socket: WebSocket, mut events: broadcast::Receiver<Event>, every: Duration, user: CurrentUser) {
+    tracing::info!(user = %user.username, "WebSocket opened");
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+    let mut alive = true;
+
+    let reason = loop {
+        let event = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => Event::Lagged { missed },
+                Err(RecvError::Closed) => break "the server is stopping",
+            },
+            _ = heartbeat.tick() => {
+                if !alive {
+                    break "no answer to the last ping";
+                }
+                alive = false;
+                if send(&mut socket, Message::Ping(Default::default())).await.is_err() {
+                    break "the client stopped reading";
+                }
+                continue;
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | None => break "the client closed it",
+                Some(Ok(_)) => {
+                    alive = true;
+                    continue;
+                }
+                Some(Err(_)) => break "the connection failed",
+            },
+        };
+
+        let text = match serde_json::to_string(&event) {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::error!("Could not serialize {:?}: {}", event, e);
+                continue;
+            }
+        };
+        if send(&mut socket, Message::Text(text.into())).await.is_err() {
+            break "the client stopped reading";
+        }
+    };
+
+    tracing::info!(user = %user.username, "WebSocket closed: {}", reason);
+    let _ = send(&mut socket, Message::Close(None)).await;
+}
+
+/// Sending waits while the client's receive buffer is full, so it gets a deadline too.
+async fn send(socket: &mut WebSocket, message: Message) -> Result<(), ()> {
+    match tokio::time::timeout(SEND_TIMEOUT, socket.send(message)).await {
+        Ok(Ok(())) => Ok(()),
+        _ => Err(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Role;
+    use crate::{AppState, app, server};
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode, header},
+    };
+    use futures_util::StreamExt;
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+    use tower::ServiceExt;
+
+    /// The app, served on a free port so a real WebSocket client can connect, and an admin's token for it.
+    async fn start(events: Events) -> (String, Router, String) {
+        let mut state = AppState::for_tests().await;
+        state.events = events;
+        let admin = CurrentUser { id: 1, username: "admin".to_string(), role: Role::Admin };
+        let (token, _) = state.keys.issue(&admin).unwrap();
+        let app = app(state);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        tokio::spawn(server::serve(listener, app.clone(), std::future::pending(), Duration::from_secs(1)));
+        (url, app, token)
+    }
+
+    async fn next_event<S>(socket: &mut S) -> Value
+    where
+        S: StreamExt<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+    {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next()).await.unwrap().unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn subscribers_hear_about_changes() {
+        let (url, app, token) = start(Events::new()).await;
+        let url = format!("{}?access_token={}", url, token);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let create = Request::post("/todos")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"title": "Buy milk"}"#))
+            .unwrap();
+        assert_eq!(app.clone().oneshot(create).await.unwrap().status(), StatusCode::CREATED);
+        let todo = json!({ "id": 1, "title": "Buy milk", "completed": false });
+        assert_eq!(next_event(&mut socket).await, json!({ "type": "todo_created", "todo": todo }));
+
+        let delete = Request::delete("/todos/1")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(app.oneshot(delete).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(next_event(&mut socket).await, json!({ "type": "todo_deleted", "id": 1 }));
+    }
+
+    #[tokio::test]
+    async fn upgrading_needs_a_token() {
+        let (url, _, token) = start(Events::new()).await;
+
+        for url in [url.clone(), format!("{}?access_token=forged", url)] {
+            match tokio_tungstenite::connect_async(url).await {
+                Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), StatusCode::UNAUTHORIZED),
+                other => panic!("expected a 401, got {:?}", other.map(|(_, response)| response)),
+            }
+        }
+
+        // The Authorization header works too, for clients that can send one.
+        let mut request = url.into_client_request().unwrap();
+        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        tokio_tungstenite::connect_async(request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_subscriber_that_falls_behind_is_told() {
+        let events = Events::with(2, HEARTBEAT);
+        let (url, _, token) = start(events.clone()).await;
+        let url = format!("{}?access_token={}", url, token);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        // The test runtime has one thread, so the connection's task can't take any of these until they're all in.
+        for id in 1..=5 {
+            events.publish(Event::TodoDeleted { id });
+        }
+        assert_eq!(next_event(&mut socket).await, json!({ "type": "lagged", "missed": 3 }));
+        assert_eq!(next_event(&mut socket).await, json!({ "type": "todo_deleted", "id": 4 }));
+        assert_eq!(next_event(&mut socket).await, json!({ "type": "todo_deleted", "id": 5 }));
+    }
+
+    #[tokio::test]
+    async fn a_client_that_never_answers_a_ping_is_closed() {
+        let heartbeat = Duration::from_millis(100);
+        let (url, _, token) = start(Events::with(CAPACITY, heartbeat)).await;
+        let url = format!("{}?access_token={}", url, token);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        // tungstenite only answers a ping while it's being read from, so not reading means never answering.
+        tokio::time::sleep(heartbeat * 3).await;
+        let closed = async {
+            loop {
+                match socket.next().await.unwrap().unwrap() {
+                    tungstenite::Message::Ping(_) => continue,
+                    tungstenite::Message::Close(_) => break,
+                    other => panic!("expected a ping or the close, got {:?}", other),
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), closed).await.expect("the connection was never closed");
+    }
+}
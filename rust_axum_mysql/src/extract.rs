@@ -0,0 +1,32 @@
+use crate::problem::Problem;
+use axum::{
+    extract::{FromRequest, FromRequestParts},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+/**
+    axum's `Json`, `Query` and `Path` extractors, but rejecting with a
+    `Problem` instead of a plain-text body.
+
+    `#[from_request(via(...))]` runs the wrapped extractor and converts its
+    rejection with `From`, which `problem.rs` implements. This is the
+    pattern axum's own "customize-extractor-error" example uses.
+*/
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(Problem))]
+pub struct AppJson<T>(pub T);
+
+impl<T: Serialize> IntoResponse for AppJson<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(Problem))]
+pub struct AppQuery<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(Problem))]
+pub struct AppPath<T>(pub T);
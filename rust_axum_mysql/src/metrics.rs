@@ -0,0 +1,219 @@
+use axum::{
+    extract::{FromRef, MatchedPath, Request, State},
+    http::{Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::time::Instant;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+/// The route label of a request no route matched, so scanners trying random paths add one series, not thousands.
+const UNMATCHED: &str = "unmatched";
+
+/**
+    GET /metrics, in the Prometheus text format, for Prometheus to scrape:
+
+        scrape_configs:
+          - job_name: todos
+            static_configs:
+              - targets: ["localhost:3001"]
+
+    It's public like /health. What's in it is traffic counts, not data, but
+    a deployment that cares can keep the path off its public load balancer.
+*/
+pub fn router<S>() -> OpenApiRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Metrics: FromRef<S>,
+{
+    OpenApiRouter::new().routes(routes!(render))
+}
+
+/**
+    The server's metrics:
+
+        http_requests_total              counter    method, route, status
+        http_request_duration_seconds    histogram  method, route, status
+        http_requests_in_flight          gauge      method, route
+        process_*                        CPU seconds, memory, open files, ... (Linux only)
+
+    What are these types?
+        A counter only goes up; Prometheus works out the rate from how fast
+        (rate(http_requests_total[5m])). A gauge goes up and down. A
+        histogram counts observations into buckets (under 5ms, under 10ms,
+        ...) so percentiles can be worked out afterwards, across instances:
+
+            histogram_quantile(0.99, sum by (le, route) (rate(http_request_duration_seconds_bucket[5m])))
+
+    Why the route template and not the path?
+        Every distinct set of labels is a separate series Prometheus keeps.
+        /todos/1, /todos/2, ... would be a new series per todo, while
+        /todos/{id} is one.
+
+    Each AppState has its own Registry rather than using the process-wide
+    default, so the tests, which each build an app, don't count each
+    other's requests.
+*/
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    duration: HistogramVec,
+    in_flight: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests answered"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time from receiving a request to answering it"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let in_flight = IntGaugeVec::new(
+            Opts::new("http_requests_in_flight", "HTTP requests being handled right now"),
+            &["method", "route"],
+        )
+        .expect("valid metric");
+
+        // Registering only fails on a name used twice, which these four can't be.
+        registry.register(Box::new(requests.clone())).expect("registered once");
+        registry.register(Box::new(duration.clone())).expect("registered once");
+        registry.register(Box::new(in_flight.clone())).expect("registered once");
+        #[cfg(target_os = "linux")]
+        {
+            let process = prometheus::process_collector::ProcessCollector::for_self();
+            registry.register(Box::new(process)).expect("registered once");
+        }
+
+        Metrics { registry, requests, duration, in_flight }
+    }
+
+    fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Encoding the metrics failed: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+/// Prometheus metrics
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses((status = 200, description = "Every metric, in the Prometheus text format", body = String,
+        content_type = "text/plain; version=0.0.4")),
+)]
+async fn render(State(metrics): State<Metrics>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], metrics.render())
+}
+
+/**
+    The middleware that measures every request, in the ServiceBuilder stack in app().
+
+    MatchedPath, the route template, is only known once the router has
+    picked a route, which is why this is added with Router::layer: that
+    wraps each route after matching, so the extension is there by the
+    time a request gets here.
+*/
+pub async fn track(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => UNMATCHED.to_string(),
+    };
+    // Any token is a valid method, so anything unusual is lumped together like unmatched routes are.
+    let method = match *request.method() {
+        Method::GET | Method::POST | Method::PUT | Method::PATCH | Method::DELETE | Method::HEAD | Method::OPTIONS => {
+            request.method().to_string()
+        }
+        _ => "other".to_string(),
+    };
+
+    let _in_flight = InFlight::start(&metrics, &method, &route);
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    metrics.requests.with_label_values(&labels).inc();
+    metrics.duration.with_label_values(&labels).observe(started.elapsed().as_secs_f64());
+    response
+}
+
+/**
+    Counts a request in flight until it's dropped.
+
+    Why not just decrement after next.run()?
+        A request can stop without ever getting there: the client hangs up,
+        or the shutdown deadline abandons it, and its future is dropped
+        mid-await. The gauge would then stay one too high forever, while
+        Drop runs either way.
+*/
+struct InFlight(prometheus::IntGauge);
+
+impl InFlight {
+    fn start(metrics: &Metrics, method: &str, route: &str) -> InFlight {
+        let gauge = metrics.in_flight.with_label_values(&[method, route]);
+        gauge.inc();
+        InFlight(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AppState, app};
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, StatusCode, header},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn counts_requests_by_route_template() {
+        let app = app(AppState::for_tests().await);
+        for uri in ["/todos", "/todos", "/todos/7", "/todos/8", "/no/such/page"] {
+            app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+        }
+
+        let response = app.oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+
+        let expected = [
+            r#"http_requests_total{method="GET",route="/todos",status="200"} 2"#,
+            r#"http_requests_total{method="GET",route="/todos/{id}",status="404"} 2"#,
+            r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#,
+            r#"http_request_duration_seconds_count{method="GET",route="/todos/{id}",status="404"} 2"#,
+            // The scrape itself is still being answered.
+            r#"http_requests_in_flight{method="GET",route="/metrics"} 1"#,
+            r#"http_requests_in_flight{method="GET",route="/todos"} 0"#,
+        ];
+        for line in expected {
+            assert!(text.lines().any(|l| l == line), "no {} in\n{}", line, text);
+        }
+        #[cfg(target_os = "linux")]
+        assert!(text.contains("process_resident_memory_bytes"), "{}", text);
+    }
+}
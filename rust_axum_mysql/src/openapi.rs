@@ -0,0 +1,203 @@
+use crate::events::Event;
+use crate::problem::PROBLEM;
+use axum::Router;
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        ContentBuilder, Ref, RefOr, ResponseBuilder,
+        header::HeaderBuilder,
+        schema::{Object, Type},
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+/**
+    The parts of the OpenAPI document that don't belong to any one route.
+
+    Where does the rest come from?
+        Every handler has a #[utoipa::path] saying its method, path,
+        parameters and responses, and every type in a body derives
+        ToSchema. Each module's router adds its handlers with
+        OpenApiRouter::routes(routes!(handler)), which registers the route
+        with axum and its documentation with the document in one go, and
+        won't compile for a handler without the attribute. app() then
+        splits the merged router into the axum Router and the document.
+
+    The document is OpenAPI 3.1, which is what lets a client generator
+    (openapi-typescript, openapi-generator, ...) produce the types and
+    calls the frontend would otherwise write by hand.
+*/
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Todos API",
+        description = "Todos, and the accounts that may change them. Errors are RFC 9457 problem details."
+    ),
+    modifiers(&BearerAuth),
+    // Sent over /ws rather than in a response body, so no route would bring it in.
+    components(schemas(Event)),
+    tags(
+        (name = "todos", description = "Reading is open to everyone, changing needs a login, deleting an admin"),
+        (name = "auth", description = "Accounts and bearer tokens"),
+        (name = "events", description = "Live updates over a WebSocket"),
+        (name = "operations", description = "For load balancers and monitoring"),
+    ),
+)]
+pub struct ApiDoc;
+
+/// The "bearer" scheme the protected operations name in their `security`, which is what gives Swagger UI its
+/// Authorize button.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let bearer = HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build();
+        components.add_security_scheme("bearer", SecurityScheme::Http(bearer));
+    }
+}
+
+/**
+    Serves the finished document at /openapi.json and Swagger UI at /docs.
+
+    Swagger UI's files are compiled into the binary (the "vendored"
+    feature), so /docs works offline and doesn't load anything from a CDN.
+
+    Any request can be turned away by the rate limiter, so a 429 is added to
+    every operation here, after all the routes' own documentation has been
+    merged in.
+*/
+pub fn docs<S>(mut openapi: utoipa::openapi::OpenApi) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    add_too_many_requests(&mut openapi);
+    SwaggerUi::new("/docs").url("/openapi.json", openapi).into()
+}
+
+fn add_too_many_requests(openapi: &mut utoipa::openapi::OpenApi) {
+    let seconds = |description| {
+        HeaderBuilder::new().schema(Object::with_type(Type::Integer)).description(Some(description)).build()
+    };
+    let response = ResponseBuilder::new()
+        .description("Over the rate limit")
+        .header("retry-after", seconds("Seconds until a request would be let through"))
+        .header("ratelimit-limit", seconds("Requests allowed in a burst"))
+        .header("ratelimit-remaining", seconds("Requests left right now"))
+        .header("ratelimit-reset", seconds("Seconds until the full burst is available again"))
+        .content(PROBLEM, ContentBuilder::new().schema(Some(Ref::from_schema_name("Problem"))).build())
+        .build();
+    let components = openapi.components.get_or_insert_with(Default::default);
+    components.responses.insert("TooManyRequests".to_string(), response.into());
+
+    for item in openapi.paths.paths.values_mut() {
+        let operations = [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.options,
+            &mut item.head,
+            &mut item.patch,
+            &mut item.trace,
+        ];
+        for operation in operations.into_iter().flatten() {
+            let too_many = RefOr::Ref(Ref::from_response_name("TooManyRequests"));
+            operation.responses.responses.entry("429".to_string()).or_insert(too_many);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AppState, app};
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, StatusCode},
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    /**
+        A route added with plain .route() instead of .routes(routes!(..))
+        works, but is missing from /openapi.json. axum can't list a
+        Router's routes, so this reads the source instead: outside comments
+        and tests, nothing may call .route( or .route_service(.
+    */
+    #[test]
+    fn every_route_is_documented() {
+        let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in std::fs::read_dir(src).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            let code = without_comments(source.split("#[cfg(test)]\nmod tests").next().unwrap());
+            for call in [".route(", ".route_service("] {
+                assert!(
+                    !code.contains(call),
+                    "{} calls {}, add the route with .routes(routes!(handler)) and a #[utoipa::path] instead",
+                    path.display(),
+                    call
+                );
+            }
+        }
+    }
+
+    fn without_comments(source: &str) -> String {
+        let mut code = String::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("/*") {
+            code.push_str(&rest[..start]);
+            rest = rest[start..].find("*/").map_or("", |end| &rest[start + end + 2..]);
+        }
+        code.push_str(rest);
+        code.lines().map(|line| line.split("//").next().unwrap()).collect::<Vec<_>>().join("\n")
+    }
+
+    #[tokio::test]
+    async fn serves_the_document_and_swagger_ui() {
+        let app = app(AppState::for_tests().await);
+
+        let response = app.clone().oneshot(Request::get("/openapi.json").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let openapi: Value = serde_json::from_slice(&body).unwrap();
+        assert!(openapi["openapi"].as_str().unwrap().starts_with("3.1"));
+
+        let paths = openapi["paths"].as_object().unwrap();
+        let mut documented: Vec<&str> = paths.keys().map(String::as_str).collect();
+        documented.sort();
+        let expected = [
+            "/auth/login",
+            "/auth/me",
+            "/auth/register",
+            "/health",
+            "/hello",
+            "/metrics",
+            "/sample",
+            "/sample2",
+            "/todos",
+            "/todos/{id}",
+            "/ws",
+        ];
+        assert_eq!(documented, expected);
+        for (path, item) in paths {
+            for (method, operation) in item.as_object().unwrap() {
+                let what = format!("{} {}", method, path);
+                assert!(operation["summary"].is_string(), "{} has no summary, give its handler a doc comment", what);
+                assert!(operation["responses"]["429"].is_object(), "{} doesn't mention the rate limit", what);
+            }
+        }
+        assert_eq!(paths["/todos/{id}"]["delete"]["security"][0]["bearer"], Value::Array(vec![]));
+        assert_eq!(openapi["components"]["securitySchemes"]["bearer"]["scheme"], "bearer");
+        let schemas = ["Todo", "CreateTodo", "UpdateTodo", "Problem", "FieldError", "Credentials", "Token"];
+        for schema in schemas.into_iter().chain(["CurrentUser", "Role", "Event"]) {
+            assert!(openapi["components"]["schemas"][schema].is_object(), "no {} schema", schema);
+        }
+
+        let response = app.oneshot(Request::get("/docs/").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("swagger-ui"));
+    }
+}
@@ -0,0 +1,170 @@
+use axum::{
+    Json,
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        ws::rejection::WebSocketUpgradeRejection,
+    },
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The media type of a problem response.
+pub const PROBLEM: &str = "application/problem+json";
+
+/**
+    An error response in the "problem details" format of RFC 9457.
+
+    Clients get the same shape for every error, whether it came from a failed
+    validation, a missing todo or a body that isn't JSON at all:
+
+        HTTP/1.1 422 Unprocessable Entity
+        Content-Type: application/problem+json
+
+        {
+            "type": "about:blank",
+            "title": "Unprocessable Entity",
+            "status": 422,
+            "detail": "The request has invalid fields",
+            "errors": [{ "field": "title", "message": "must not be empty" }]
+        }
+
+    `type` stays "about:blank", which per the RFC means the status code says
+    it all and `title` is just its reason phrase.
+*/
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(example = json!({
+    "type": "about:blank",
+    "title": "Not Found",
+    "status": 404,
+    "detail": "There is no todo with id 7"
+}))]
+pub struct Problem {
+    #[serde(rename = "type")]
+    #[schema(value_type = String)]
+    kind: &'static str,
+    title: String,
+    #[serde(serialize_with = "serialize_status")]
+    #[schema(value_type = u16)]
+    status: StatusCode,
+    detail: String,
+    /// Only on a 422.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+/// One invalid field of a request.
+#[derive(Debug, Serialize, PartialEq, ToSchema)]
+pub struct FieldError {
+    #[schema(value_type = String)]
+    pub field: &'static str,
+    pub message: String,
+}
+
+fn serialize_status<S: serde::Serializer>(status: &StatusCode, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u16(status.as_u16())
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Problem {
+        Problem {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status,
+            detail: detail.into(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Problem {
+        Problem::new(StatusCode::NOT_FOUND, detail)
+    }
+
+    /// 401: no credentials, or ones that didn't check out. Log in (again) and retry.
+    pub fn unauthorized(detail: impl Into<String>) -> Problem {
+        Problem::new(StatusCode::UNAUTHORIZED, detail)
+    }
+
+    /// 403: the credentials are fine, they just don't allow this.
+    pub fn forbidden(detail: impl Into<String>) -> Problem {
+        Problem::new(StatusCode::FORBIDDEN, detail)
+    }
+
+    pub fn conflict(detail: impl Into<String>) -> Problem {
+        Problem::new(StatusCode::CONFLICT, detail)
+    }
+
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+
+    /// 422 listing every invalid field, so a client can fix them all at once.
+    pub fn invalid(errors: Vec<FieldError>) -> Problem {
+        Problem { errors, ..Problem::new(StatusCode::UNPROCESSABLE_ENTITY, "The request has invalid fields") }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = self.status;
+        let mut response = (status, Json(self)).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, PROBLEM.parse().expect("valid header value"));
+        // RFC 9110 requires a 401 to say which authentication scheme would work.
+        if status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, "Bearer".parse().expect("valid header value"));
+        }
+        response
+    }
+}
+
+/*
+    Failures on the server's side: the details stay in the server log, the
+    client only learns that it wasn't their fault.
+*/
+
+impl From<jsonwebtoken::errors::Error> for Problem {
+    fn from(error: jsonwebtoken::errors::Error) -> Problem {
+        tracing::error!("Could not sign a token: {}", error);
+        Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "Could not sign a token")
+    }
+}
+
+impl From<sqlx::Error> for Problem {
+    fn from(error: sqlx::Error) -> Problem {
+        tracing::error!("Database error: {}", error);
+        Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "The database could not complete the request")
+    }
+}
+
+/*
+    The rejections of axum's own extractors become problems too, so a bad
+    body, query string or path reads the same as any other error. The
+    rejection already picks the right status (400, 415, 422) and message.
+*/
+
+impl From<JsonRejection> for Problem {
+    fn from(rejection: JsonRejection) -> Problem {
+        Problem::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for Problem {
+    fn from(rejection: QueryRejection) -> Problem {
+        Problem::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for Problem {
+    fn from(rejection: PathRejection) -> Problem {
+        Problem::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<WebSocketUpgradeRejection> for Problem {
+    fn from(rejection: WebSocketUpgradeRejection) -> Problem {
+        Problem::new(rejection.status(), rejection.body_text())
+    }
+}
@@ -0,0 +1,468 @@
+use crate::auth::CurrentUser;
+use crate::problem::Problem;
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower::{Layer, Service};
+
+/// The route groups a limit can be set for. "global" covers every request.
+pub const GROUPS: [&str; 3] = ["global", "auth", "todos"];
+/// How many buckets the in-memory store keeps at most.
+const MAX_BUCKETS: usize = 100_000;
+/// How often the in-memory store clears out the buckets that are full again.
+const PRUNE_EVERY: Duration = Duration::from_secs(60);
+
+/**
+    A limit like "10/m": up to 10 requests at once, refilling at 10 a minute.
+
+    Written as `<requests>/<period>`, where the period is s, m or h, or a
+    number of seconds like 30s. "off" turns a group's limit off.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Limit {
+    Off,
+    Quota { requests: u32, period: Duration },
+}
+
+impl FromStr for Limit {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Limit, String> {
+        if text == "off" {
+            return Ok(Limit::Off);
+        }
+        let bad = || format!("expected <requests>/<period> like 10/m, 100/h or 5/30s, or off, got \"{}\"", text);
+        let (requests, period) = text.split_once('/').ok_or_else(bad)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| bad())?;
+        let seconds = match period.trim() {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            period => period.strip_suffix('s').and_then(|n| n.parse().ok()).ok_or_else(bad)?,
+        };
+        if requests == 0 || seconds == 0 {
+            return Err(format!("\"{}\" would refuse every request, use off to turn the limit off instead", text));
+        }
+        Ok(Limit::Quota { requests, period: Duration::from_secs(seconds) })
+    }
+}
+
+impl TryFrom<String> for Limit {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Limit, String> {
+        text.parse()
+    }
+}
+
+/// What a store decided about one request, and what to tell the client about their budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the bucket is full again.
+    pub reset: Duration,
+    /// Until the next request would be allowed; zero when this one was.
+    pub retry_after: Duration,
+}
+
+pub type StoreFuture<'a> = Pin<Box<dyn Future<Output = Result<Decision, String>> + Send + 'a>>;
+
+/**
+    Where the buckets live.
+
+    The in-memory store is right for one server. Behind a load balancer each
+    instance would count separately, letting a client through N times as
+    often, so a deployment like that implements this trait over a store the
+    instances share (Redis, say, with the same arithmetic in a Lua script)
+    and puts it in AppState::rate_limits.
+
+    Why a boxed future instead of `async fn`?
+        `async fn` in a trait makes it impossible to use as `dyn
+        RateLimitStore`, and the point here is to choose the store at
+        runtime. Boxing the future is what async_trait would do for us.
+*/
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Takes one token from `key`'s bucket if there's one to take.
+    fn take<'a>(&'a self, key: &'a str, requests: u32, period: Duration) -> StoreFuture<'a>;
+}
+
+/**
+    Token buckets in a HashMap.
+
+    What is a token bucket?
+        Every client has a bucket holding up to `requests` tokens. A request
+        takes one, and tokens drip back in at `requests` per `period`. A
+        client that's been quiet can send a burst of the whole bucket at
+        once, one that keeps sending gets the steady rate and no more.
+
+    Only a number and a time are kept per bucket: the tokens there were at
+    the last request, topped up by however many dripped in since.
+
+    How does the map stay small?
+        A full bucket is the same as no bucket, so every PRUNE_EVERY the
+        full ones are dropped. That can't help when more clients than
+        MAX_BUCKETS are busy at once (rotating IPv6 addresses, say), so
+        past that the tenth that were seen longest ago are forgotten too,
+        which at worst gives them a fresh bucket. Either way the work is
+        done once in a while, not on every request.
+*/
+pub struct InMemoryStore {
+    buckets: Mutex<Buckets>,
+    max_buckets: usize,
+}
+
+#[derive(Default)]
+struct Buckets {
+    map: HashMap<String, Bucket>,
+    pruned: Option<Instant>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant,
+}
+
+impl InMemoryStore {
+    pub fn with_capacity(max_buckets: usize) -> InMemoryStore {
+        InMemoryStore { buckets: Mutex::default(), max_buckets }
+    }
+
+    fn take_now(&self, key: &str, requests: u32, period: Duration, now: Instant) -> Decision {
+        let capacity = requests as f64;
+        let per_second = capacity / period.as_secs_f64();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if buckets.pruned.is_none_or(|pruned| now.saturating_duration_since(pruned) >= PRUNE_EVERY) {
+            buckets.map.retain(|_, bucket| bucket.full_at > now);
+            buckets.pruned = Some(now);
+        }
+        if buckets.map.len() >= self.max_buckets && !buckets.map.contains_key(key) {
+            buckets.forget_oldest();
+        }
+        let bucket =
+            buckets.map.entry(key.to_string()).or_insert(Bucket { tokens: capacity, updated: now, full_at: now });
+
+        let dripped = now.saturating_duration_since(bucket.updated).as_secs_f64() * per_second;
+        bucket.tokens = (bucket.tokens + dripped).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let reset = Duration::from_secs_f64((capacity - bucket.tokens) / per_second);
+        bucket.full_at = now + reset;
+        let retry_after =
+            if allowed { Duration::ZERO } else { Duration::from_secs_f64((1.0 - bucket.tokens) / per_second) };
+
+        Decision { allowed, limit: requests, remaining: bucket.tokens as u32, reset, retry_after }
+    }
+}
+
+impl Default for InMemoryStore {
+    fn default() -> InMemoryStore {
+        InMemoryStore::with_capacity(MAX_BUCKETS)
+    }
+}
+
+impl Buckets {
+    /// Drops at least a tenth of the buckets, the ones used longest ago.
+    fn forget_oldest(&mut self) {
+        if self.map.is_empty() {
+            return;
+        }
+        let mut updated: Vec<Instant> = self.map.values().map(|bucket| bucket.updated).collect();
+        let tenth = updated.len() / 10;
+        let (_, &mut cutoff, _) = updated.select_nth_unstable(tenth);
+        self.map.retain(|_, bucket| bucket.updated > cutoff);
+    }
+}
+
+impl RateLimitStore for InMemoryStore {
+    fn take<'a>(&'a self, key: &'a str, requests: u32, period: Duration) -> StoreFuture<'a> {
+        Box::pin(async move { Ok(self.take_now(key, requests, period, Instant::now())) })
+    }
+}
+
+/**
+    Who a request counts against.
+
+    A logged-in user is counted by account, wherever they connect from:
+    their bearer token is this server's API key. Everyone else is counted
+    by IP address. That relies on auth::authenticate having run first, so
+    every limiter sits inside it (see app()).
+
+    Why not trust X-Forwarded-For?
+        Any client can send that header, so keying on it would let anyone
+        pick a fresh identity per request. Behind a reverse proxy the proxy
+        should do the per-IP limiting, or this should be changed to read
+        the header the proxy sets and nothing else.
+*/
+fn client_key(req: &Request) -> String {
+    if let Some(user) = req.extensions().get::<CurrentUser>() {
+        return format!("user:{}", user.id);
+    }
+    match req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(address)) => format!("ip:{}", address.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/**
+    Rate limits one group of routes, as a tower layer:
+
+        .layer(RateLimitLayer::new("global", limit, store.clone()))           // every route
+        .route_layer(RateLimitLayer::new("auth", limit, store.clone()))       // just these
+
+    A request over the limit gets a 429 with Retry-After, and every response
+    carries the client's budget in the RateLimit-* headers of the IETF
+    draft: RateLimit-Limit, RateLimit-Remaining and RateLimit-Reset, all in
+    requests or whole seconds. When two groups apply, the inner (more
+    specific) one's headers are the ones the client sees.
+
+    If the store fails, the request is let through: a broken rate limiter
+    shouldn't take the whole API down with it.
+*/
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    group: &'static str,
+    limit: Limit,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimitLayer {
+    pub fn new(group: &'static str, limit: Limit, store: Arc<dyn RateLimitStore>) -> RateLimitLayer {
+        RateLimitLayer { group, limit, store }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> RateLimit<S> {
+        RateLimit { layer: self.clone(), inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    layer: RateLimitLayer,
+    inner: S,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        /*
+            `inner` was the one made ready by poll_ready, so that's the one to
+            call. Swapping a fresh clone into `self` leaves the ready one for
+            this request, which the async block needs to own anyway.
+        */
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let RateLimitLayer { group, limit, store } = self.layer.clone();
+
+        Box::pin(async move {
+            let Limit::Quota { requests, period } = limit else {
+                return inner.call(req).await;
+            };
+            let key = format!("{}:{}", group, client_key(&req));
+            let decision = match store.take(&key, requests, period).await {
+                Ok(decision) => decision,
+                Err(e) => {
+                    tracing::warn!("Rate limit store failed, letting the request through: {}", e);
+                    return inner.call(req).await;
+                }
+            };
+
+            let mut response = if decision.allowed {
+                inner.call(req).await?
+            } else {
+                let detail = format!("Too many requests, try again in {} seconds", seconds(decision.retry_after));
+                let mut response = Problem::new(StatusCode::TOO_MANY_REQUESTS, detail).into_response();
+                response.headers_mut().insert(header::RETRY_AFTER, seconds(decision.retry_after).into());
+                response
+            };
+            add_headers(response.headers_mut(), &decision);
+            Ok(response)
+        })
+    }
+}
+
+/// Whole seconds, rounded up so a client that waits that long is sure to get through.
+fn seconds(duration: Duration) -> u64 {
+    duration.as_secs_f64().ceil() as u64
+}
+
+/// What a client reads its budget from.
+pub const HEADERS: [HeaderName; 4] = [
+    HeaderName::from_static("ratelimit-limit"),
+    HeaderName::from_static("ratelimit-remaining"),
+    HeaderName::from_static("ratelimit-reset"),
+    header::RETRY_AFTER,
+];
+
+fn add_headers(headers: &mut HeaderMap, decision: &Decision) {
+    let [limit, remaining, reset, _] = HEADERS;
+    let values = [
+        (limit, decision.limit as u64),
+        (remaining, decision.remaining as u64),
+        (reset, seconds(decision.reset)),
+    ];
+    for (name, value) in values {
+        headers.entry(name).or_insert(HeaderValue::from(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Role;
+    use crate::{AppState, app};
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    #[test]
+    fn parses_limits() {
+        let quota = |requests, seconds| Limit::Quota { requests, period: Duration::from_secs(seconds) };
+        assert_eq!("10/m".parse(), Ok(quota(10, 60)));
+        assert_eq!("100/h".parse(), Ok(quota(100, 3600)));
+        assert_eq!("5/30s".parse(), Ok(quota(5, 30)));
+        assert_eq!("off".parse(), Ok(Limit::Off));
+        for bad in ["10", "ten/m", "10/d", "0/m", "10/0s"] {
+            assert!(bad.parse::<Limit>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn buckets_empty_and_refill() {
+        let store = InMemoryStore::default();
+        let start = Instant::now();
+        let take = |key, after| store.take_now(key, 3, Duration::from_secs(60), start + Duration::from_secs(after));
+
+        let burst: Vec<u32> = (0..3).map(|_| take("a", 0)).map(|decision| decision.remaining).collect();
+        assert_eq!(burst, vec![2, 1, 0]);
+        let refused = take("a", 0);
+        assert!(!refused.allowed);
+        assert_eq!(refused.retry_after, Duration::from_secs(20));
+        assert_eq!(refused.reset, Duration::from_secs(60));
+
+        // Someone else has a bucket of their own.
+        assert!(take("b", 0).allowed);
+
+        // One token drips back every 20 seconds.
+        assert!(take("a", 20).allowed);
+        assert!(!take("a", 20).allowed);
+        assert_eq!(take("a", 120).remaining, 2);
+    }
+
+    #[test]
+    fn the_store_stays_bounded() {
+        let store = InMemoryStore::with_capacity(10);
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        let take = |key: &str, after| store.take_now(key, 1, minute, start + Duration::from_secs(after));
+        let size = || store.buckets.lock().unwrap().map.len();
+
+        // More busy clients than fit: the ones seen longest ago make room.
+        for n in 0..25 {
+            take(&format!("client{}", n), n);
+        }
+        assert!(size() <= 10, "{} buckets", size());
+        assert!(!take("client24", 25).allowed, "the latest client was forgotten");
+
+        // Buckets that have filled up again are cleared out once a minute.
+        take("late", 90);
+        assert_eq!(size(), 1);
+    }
+
+    #[tokio::test]
+    async fn over_the_limit_gets_429() {
+        let mut state = AppState::for_tests().await;
+        let mut config = (*state.config).clone();
+        config.rate_limits.insert("auth".to_string(), "2/m".parse().unwrap());
+        state.config = Arc::new(config);
+        let app = app(state);
+
+        let from = |ip: [u8; 4]| {
+            let mut request = Request::get("/auth/me").body(Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::from((ip, 4000))));
+            request
+        };
+
+        let response = app.clone().oneshot(from([10, 0, 0, 1])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["ratelimit-limit"], "2");
+        assert_eq!(response.headers()["ratelimit-remaining"], "1");
+        app.clone().oneshot(from([10, 0, 0, 1])).await.unwrap();
+
+        let response = app.clone().oneshot(from([10, 0, 0, 1])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+        assert_eq!(response.headers()["ratelimit-remaining"], "0");
+        assert_eq!(response.headers()["ratelimit-reset"], "60");
+
+        // Another address, and routes outside the group, aren't affected.
+        let response = app.clone().oneshot(from([10, 0, 0, 2])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app.oneshot(Request::get("/todos").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn logged_in_users_on_one_address_have_their_own_global_budget() {
+        let mut state = AppState::for_tests().await;
+        let mut config = (*state.config).clone();
+        config.rate_limits.insert("global".to_string(), "1/m".parse().unwrap());
+        state.config = Arc::new(config);
+        let token = |id| {
+            let user = CurrentUser { id, username: format!("user{}", id), role: Role::User };
+            state.keys.issue(&user).unwrap().0
+        };
+        let (alice, bob) = (token(1), token(2));
+        let app = app(state);
+
+        let send = |token: Option<&String>| {
+            let mut request = Request::get("/todos");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            let mut request = request.body(Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+            app.clone().oneshot(request)
+        };
+
+        assert_eq!(send(Some(&alice)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send(Some(&alice)).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(send(Some(&bob)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send(None).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send(None).await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,152 @@
+use axum::Router;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/**
+    Serves `app` until `shutdown` completes, then drains.
+
+    What does a graceful shutdown do?
+        Once `shutdown` resolves, axum stops accepting connections and tells
+        every open one to finish the request it's on and then close (an idle
+        keep-alive connection closes right away). `serve` returns when the
+        last one is gone.
+
+    Why a deadline?
+        A client that never finishes sending its body, or a handler stuck on
+        a slow query, would otherwise keep the process alive forever, and an
+        orchestrator like Kubernetes would end up killing it with SIGKILL
+        anyway, without the pool being closed or the logs written. After
+        `deadline` the requests still running are abandoned and serve returns.
+*/
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+    deadline: Duration,
+) -> std::io::Result<()> {
+    let (draining, drain_started) = oneshot::channel();
+    let signal = async move {
+        shutdown.await;
+        tracing::info!("Shutting down, waiting up to {:?} for requests in flight", deadline);
+        let _ = draining.send(());
+    };
+    // ConnectInfo gives handlers (and the rate limiter) the client's address.
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server = axum::serve(listener, app).with_graceful_shutdown(signal).into_future();
+
+    tokio::select! {
+        result = server => result,
+        _ = async {
+            // A closed channel means the server stopped by itself; let the other branch finish.
+            if drain_started.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            tokio::time::sleep(deadline).await;
+        } => {
+            tracing::warn!("Requests still running after {:?}, abandoning them", deadline);
+            Ok(())
+        }
+    }
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM (what `docker stop` and Kubernetes send) on Unix.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Can't listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Can't listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::Notify;
+
+    type Server = tokio::task::JoinHandle<std::io::Result<()>>;
+
+    /// A server whose only route waits `delay` once it has let the test know it started.
+    async fn start(delay: Duration, deadline: Duration) -> (u16, Arc<Notify>, oneshot::Sender<()>, Server) {
+        let started = Arc::new(Notify::new());
+        let handler_started = started.clone();
+        let app = Router::new().route(
+            "/slow",
+            get(move || async move {
+                handler_started.notify_one();
+                tokio::time::sleep(delay).await;
+                "done"
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, app, async { stopped.await.unwrap_or(()) }, deadline));
+        (port, started, stop, server)
+    }
+
+    async fn request_slow(port: u16) -> tokio::task::JoinHandle<String> {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        tokio::spawn(async move {
+            stream.write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        })
+    }
+
+    #[tokio::test]
+    async fn in_flight_request_finishes_after_shutdown_begins() {
+        let (port, started, stop, server) = start(Duration::from_millis(300), Duration::from_secs(5)).await;
+        let response = request_slow(port).await;
+
+        started.notified().await;
+        stop.send(()).unwrap();
+
+        let response = response.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("done"), "{}", response);
+        server.await.unwrap().unwrap();
+
+        // The listener is gone with the server.
+        assert!(TcpStream::connect(("127.0.0.1", port)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stuck_request_is_abandoned_at_the_deadline() {
+        let (port, started, stop, server) = start(Duration::from_secs(60), Duration::from_millis(100)).await;
+        let _response = request_slow(port).await;
+
+        started.notified().await;
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server).await.expect("serve returned").unwrap().unwrap();
+    }
+}
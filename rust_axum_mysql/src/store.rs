@@ -0,0 +1,99 @@
+use crate::todos::{CreateTodo, Todo, UpdateTodo};
+use sqlx::{AnyPool, Row, any::AnyRow};
+
+/**
+    Where the todos are kept: the `todos` table, reached through the pool.
+
+    Every query here is plain SQL that MySQL and SQLite both accept, with `?`
+    placeholders (the syntax both use). That's what lets db.rs swap one
+    database for the other without touching this file.
+
+    Why sqlx::query and not the sqlx::query! macro?
+        query! checks the SQL against a live database while compiling, which
+        is great, but needs DATABASE_URL at build time and one kind of
+        database. With AnyPool the database is only known at runtime.
+
+    Cloning the store is cheap: AnyPool is an Arc inside, so every clone
+    shares the same connections, which is what lets axum hand a copy to each
+    request.
+*/
+#[derive(Clone)]
+pub struct TodoStore {
+    pool: AnyPool,
+}
+
+/// `completed` is stored as an integer: the Any driver has no boolean that works on MySQL.
+fn todo_from_row(row: &AnyRow) -> Result<Todo, sqlx::Error> {
+    let id: i64 = row.try_get("id")?;
+    let completed: i64 = row.try_get("completed")?;
+    Ok(Todo { id: id as u64, title: row.try_get("title")?, completed: completed != 0 })
+}
+
+impl TodoStore {
+    pub fn new(pool: AnyPool) -> TodoStore {
+        TodoStore { pool }
+    }
+
+    /// One page of todos in creation order, and how many there are in all.
+    pub async fn list(&self, offset: u64, limit: u64) -> Result<(Vec<Todo>, u64), sqlx::Error> {
+        let rows = sqlx::query("SELECT id, title, completed FROM todos ORDER BY id LIMIT ? OFFSET ?")
+            .bind(limit as i64)
+            .bind(offset.min(i64::MAX as u64) as i64)
+            .fetch_all(&self.pool)
+            .await?;
+        let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos").fetch_one(&self.pool).await?;
+
+        let page = rows.iter().map(todo_from_row).collect::<Result<_, _>>()?;
+        Ok((page, total as u64))
+    }
+
+    pub async fn get(&self, id: u64) -> Result<Option<Todo>, sqlx::Error> {
+        let row = sqlx::query("SELECT id, title, completed FROM todos WHERE id = ?")
+            .bind(id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(todo_from_row).transpose()
+    }
+
+    pub async fn create(&self, new: CreateTodo) -> Result<Todo, sqlx::Error> {
+        // One connection for both statements: the id just assigned is only known to the connection that inserted.
+        let mut connection = self.pool.acquire().await?;
+        let result = sqlx::query("INSERT INTO todos (title, completed) VALUES (?, ?)")
+            .bind(&new.title)
+            .bind(new.completed as i64)
+            .execute(&mut *connection)
+            .await?;
+
+        // MySQL reports its AUTO_INCREMENT value with the result. SQLite through the Any driver doesn't,
+        // so ask it.
+        let id = match result.last_insert_id() {
+            Some(id) => id,
+            None => sqlx::query_scalar("SELECT last_insert_rowid()").fetch_one(&mut *connection).await?,
+        };
+        Ok(Todo { id: id as u64, title: new.title, completed: new.completed })
+    }
+
+    /**
+        Applies the fields that are set. `None` if there's no such todo.
+
+        COALESCE(?, title) keeps the current value when NULL is bound, so one
+        statement covers every combination of fields. The row is read back
+        afterwards rather than trusting rows_affected, because MySQL counts a
+        row whose values didn't change as unaffected.
+    */
+    pub async fn update(&self, id: u64, changes: UpdateTodo) -> Result<Option<Todo>, sqlx::Error> {
+        sqlx::query("UPDATE todos SET title = COALESCE(?, title), completed = COALESCE(?, completed) WHERE id = ?")
+            .bind(changes.title)
+            .bind(changes.completed.map(|completed| completed as i64))
+            .bind(id as i64)
+            .execute(&self.pool)
+            .await?;
+        self.get(id).await
+    }
+
+    /// Whether there was a todo to delete.
+    pub async fn delete(&self, id: u64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM todos WHERE id = ?").bind(id as i64).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,172 @@
+use crate::config::Config;
+use axum::http::{
+    HeaderName, Request, Response,
+    header::{AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE},
+};
+use std::time::Duration;
+use tracing::{Span, field};
+use tracing_subscriber::EnvFilter;
+
+/// Request headers whose values never reach the logs.
+pub const SENSITIVE_REQUEST_HEADERS: [HeaderName; 3] = [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION];
+pub const SENSITIVE_RESPONSE_HEADERS: [HeaderName; 1] = [SET_COOKIE];
+
+/**
+    Sends every log event to stdout, as text or as one JSON object per line.
+
+    Which events are shown?
+        RUST_LOG, when set, has the final say (e.g. RUST_LOG=rust_axum_mysql=debug,sqlx=warn),
+        otherwise the configured log level applies to everything.
+
+    JSON is for when a log shipper reads the output: every field (method, path,
+    status, request_id, ...) becomes a key instead of text to be parsed again.
+*/
+pub fn init(config: &Config) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_level));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    if config.log_format == "json" {
+        subscriber.json().with_current_span(true).with_span_list(false).init();
+    } else {
+        subscriber.init();
+    }
+}
+
+/**
+    What is axum::routing::Trace? How is it different from tracing?
+        axum::routing::trace refers specifically to routing HTTP TRACE method requests to a handler,
+        not request tracing or logging.
+        It is used to assign a handler to respond to HTTP TRACE requests,
+        similar to get or post routing functions.
+
+        If you want to trace (log and instrument) all requests and responses in an Axum app for observability
+        (which is usually what "trace requests" means), you should use tower_http::trace::TraceLayer
+
+        What is HTTP TRACE? (Interesting)
+            The HTTP TRACE method is one of the less commonly used HTTP verbs.
+            It is defined in the HTTP/1.1 specification and is intended for diagnostic purposes.
+
+            TRACE asks a web server to return the received request (including headers and body) as the response,
+            allowing the client to see how the request was received by the server along the path.
+            This can be useful for debugging proxies, firewalls, or custom network setups to
+            determine if and how intermediate components are modifying the request.
+
+    How to trace requests?
+        To trace requests in Axum, the recommended approach is to use the tracing crate
+        along with tower-http's TraceLayer middleware.
+
+        A span is a stretch of time with fields attached. TraceLayer opens one per request with
+        make_span below, and every event logged while handling that request, in any handler or
+        in sqlx, is printed with the span's fields, so one request's lines can be told apart by
+        its request_id. status and latency aren't known yet when the span opens, so they start
+        out Empty and on_response fills them in.
+
+    What is tower & tower_http?
+
+        tower is a library of modular, reusable components and middleware for building robust networking clients
+        and servers. It is protocol-agnostic, meaning its middleware works across various protocols and does not
+        have awareness of specific protocol details like HTTP status codes or headers. For example, a timeout
+        middleware in tower can apply to any service regardless of whether it uses HTTP or some other protocol.
+
+        tower_http is built on top of tower and provides HTTP-specific middleware and utilities. It uses the http
+        and http-body crates, making it compatible with HTTP clients and servers like hyper, tonic, and warp. This
+        means tower_http has middleware that understands HTTP features such as headers, status codes, compression,
+        CORS, tracing, and request validation. It offers middleware tailored to HTTP applications, such as setting
+        response headers, compressing response bodies, propagating headers, and more
+*/
+pub fn make_span<B>(request: &Request<B>) -> Span {
+    // SetRequestIdLayer runs first, so the header is always there by now.
+    let request_id = request.headers().get("x-request-id").and_then(|id| id.to_str().ok()).unwrap_or("-");
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        request_id = %request_id,
+        status = field::Empty,
+        latency_ms = field::Empty,
+    )
+}
+
+/**
+    Headers are only logged at debug. SetSensitiveRequestHeadersLayer has
+    already marked Authorization and Cookie as sensitive, and a sensitive
+    HeaderValue prints as `Sensitive` instead of its value, so tokens and
+    session cookies stay out of the logs even then.
+*/
+pub fn on_request<B>(request: &Request<B>, _span: &Span) {
+    tracing::debug!(headers = ?request.headers(), "started");
+}
+
+pub fn on_response<B>(response: &Response<B>, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+    tracing::info!("finished");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::{CurrentUser, Role};
+    use crate::{AppState, app};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    /// Collects what the subscriber writes, to look at afterwards.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_get_an_id_and_a_redacted_log() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        // The test runs on a single thread, so a thread-local subscriber sees the whole request.
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let state = AppState::for_tests().await;
+        let user = CurrentUser { id: 1, username: "alice".to_string(), role: Role::User };
+        let (token, _) = state.keys.issue(&user).unwrap();
+        let app = app(state);
+        let request = Request::get("/hello")
+            .header("authorization", format!("Bearer {}", token))
+            .header("cookie", "session=also-secret")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+        assert_eq!(id.len(), 36, "a UUID, got {}", id);
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(!logs.contains(&token) && !logs.contains("also-secret"), "{}", logs);
+        assert!(logs.contains("Sensitive"), "{}", logs);
+        let finished = logs.lines().find(|line| line.contains("\"finished\"")).expect("a finished event");
+        let finished: serde_json::Value = serde_json::from_str(finished).unwrap();
+        assert_eq!(finished["span"]["request_id"], id.as_str());
+        assert_eq!(finished["span"]["path"], "/hello");
+        assert_eq!(finished["span"]["status"], 200);
+
+        // An id the caller sent along is kept, so it can follow the request across services.
+        let request = Request::get("/hello").header("x-request-id", "from-the-caller").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["x-request-id"], "from-the-caller");
+    }
+}
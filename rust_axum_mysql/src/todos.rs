@@ -0,0 +1,403 @@
+use crate::auth;
+use crate::events::{Event, Events};
+use crate::extract::{AppJson, AppPath, AppQuery};
+use crate::problem::{FieldError, PROBLEM, Problem};
+use crate::store::TodoStore;
+use axum::{
+    extract::{FromRef, State},
+    http::{StatusCode, header},
+    middleware,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+/// Longest title a todo can have, in characters.
+const MAX_TITLE: usize = 200;
+const DEFAULT_PER_PAGE: u64 = 20;
+const MAX_PER_PAGE: u64 = 100;
+
+/**
+    The `/todos` resource:
+
+        GET    /todos?page=1&per_page=20   one page of todos
+        POST   /todos                      create one, 201 with a Location header
+        GET    /todos/{id}                 one todo
+        PATCH  /todos/{id}                 change the fields that are sent
+        DELETE /todos/{id}                 204, or 404 if it wasn't there
+
+    Every error is a `Problem` (application/problem+json), and every change
+    is published to the /ws subscribers (see events.rs).
+
+    Anyone may read. Creating and changing todos needs a logged-in user,
+    deleting needs an admin (see auth.rs). A route_layer only wraps the
+    routes added before it, so each group of routes is its own Router with
+    its own guard, merged into one at the end.
+
+    Each route is documented by the #[utoipa::path] on its handler, which
+    routes!() reads the method and path from, so the route and its entry
+    in /openapi.json can't drift apart (see openapi.rs).
+
+    Why is the router generic over S?
+        The handlers only need the `TodoStore`, not the whole application
+        state. `FromRef` lets `State<TodoStore>` be pulled out of any state
+        that contains one, so this module doesn't depend on what else the
+        app keeps in its state.
+*/
+pub fn router<S>() -> OpenApiRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+    TodoStore: FromRef<S>,
+    Events: FromRef<S>,
+{
+    let members = OpenApiRouter::new()
+        .routes(routes!(create_todo))
+        .routes(routes!(update_todo))
+        .route_layer(middleware::from_fn(auth::require_user));
+    let admins = OpenApiRouter::new()
+        .routes(routes!(delete_todo))
+        .route_layer(middleware::from_fn(auth::require_admin));
+
+    OpenApiRouter::new()
+        .routes(routes!(list_todos))
+        .routes(routes!(get_todo))
+        .merge(members)
+        .merge(admins)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct Todo {
+    pub id: u64,
+    pub title: String,
+    pub completed: bool,
+}
+
+/**
+    Body of `POST /todos`.
+
+    `deny_unknown_fields` turns a misspelled field like "complete" into a 422
+    instead of silently ignoring it.
+*/
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateTodo {
+    pub title: String,
+    #[serde(default)]
+    pub completed: bool,
+}
+
+/// Body of `PATCH /todos/{id}`. Fields that are left out stay as they are.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateTodo {
+    pub title: Option<String>,
+    pub completed: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    /// Starting at 1 (the default).
+    page: Option<u64>,
+    /// 1 to 100, 20 by default.
+    per_page: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub per_page: u64,
+    pub total: u64,
+}
+
+/// Trims the title and checks it's neither empty nor too long.
+fn validate_title(title: &str, errors: &mut Vec<FieldError>) -> String {
+    let title = title.trim();
+    if title.is_empty() {
+        errors.push(FieldError { field: "title", message: "must not be empty".to_string() });
+    } else if title.chars().count() > MAX_TITLE {
+        errors.push(FieldError { field: "title", message: format!("must be at most {} characters", MAX_TITLE) });
+    }
+    title.to_string()
+}
+
+impl Pagination {
+    fn validate(&self) -> Result<(u64, u64), Problem> {
+        let page = self.page.unwrap_or(1);
+        let per_page = self.per_page.unwrap_or(DEFAULT_PER_PAGE);
+
+        let mut errors = Vec::new();
+        if page == 0 {
+            errors.push(FieldError { field: "page", message: "must be at least 1".to_string() });
+        }
+        if !(1..=MAX_PER_PAGE).contains(&per_page) {
+            errors.push(FieldError { field: "per_page", message: format!("must be between 1 and {}", MAX_PER_PAGE) });
+        }
+
+        if errors.is_empty() { Ok((page, per_page)) } else { Err(Problem::invalid(errors)) }
+    }
+}
+
+/// List todos
+///
+/// Oldest first, one page at a time.
+#[utoipa::path(
+    get,
+    path = "/todos",
+    tag = "todos",
+    params(Pagination),
+    responses(
+        (status = 200, description = "One page of todos", body = Page<Todo>),
+        (status = 422, description = "page or per_page is out of range", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn list_todos(
+    State(store): State<TodoStore>,
+    AppQuery(pagination): AppQuery<Pagination>,
+) -> Result<AppJson<Page<Todo>>, Problem> {
+    let (page, per_page) = pagination.validate()?;
+    let (items, total) = store.list((page - 1).saturating_mul(per_page), per_page).await?;
+    Ok(AppJson(Page { items, page, per_page, total }))
+}
+
+/// Get a todo
+#[utoipa::path(
+    get,
+    path = "/todos/{id}",
+    tag = "todos",
+    params(("id" = u64, Path, description = "The todo's id")),
+    responses(
+        (status = 200, description = "The todo", body = Todo),
+        (status = 404, description = "There's no todo with that id", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn get_todo(State(store): State<TodoStore>, AppPath(id): AppPath<u64>) -> Result<AppJson<Todo>, Problem> {
+    store.get(id).await?.map(AppJson).ok_or_else(|| not_found(id))
+}
+
+/// Create a todo
+///
+/// Needs a logged-in user.
+#[utoipa::path(
+    post,
+    path = "/todos",
+    tag = "todos",
+    request_body = CreateTodo,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, description = "Created", body = Todo,
+            headers(("location" = String, description = "Where the new todo is, e.g. /todos/1"))),
+        (status = 401, description = "Not logged in", body = Problem, content_type = PROBLEM),
+        (status = 422, description = "The title is empty or too long", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn create_todo(
+    State(store): State<TodoStore>,
+    State(events): State<Events>,
+    AppJson(mut new): AppJson<CreateTodo>,
+) -> Result<impl IntoResponse, Problem> {
+    let mut errors = Vec::new();
+    new.title = validate_title(&new.title, &mut errors);
+    if !errors.is_empty() {
+        return Err(Problem::invalid(errors));
+    }
+
+    let todo = store.create(new).await?;
+    events.publish(Event::TodoCreated { todo: todo.clone() });
+    let location = format!("/todos/{}", todo.id);
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], AppJson(todo)))
+}
+
+/// Change a todo
+///
+/// Only the fields that are sent change. Needs a logged-in user.
+#[utoipa::path(
+    patch,
+    path = "/todos/{id}",
+    tag = "todos",
+    params(("id" = u64, Path, description = "The todo's id")),
+    request_body = UpdateTodo,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The todo as it is now", body = Todo),
+        (status = 401, description = "Not logged in", body = Problem, content_type = PROBLEM),
+        (status = 404, description = "There's no todo with that id", body = Problem, content_type = PROBLEM),
+        (status = 422, description = "The title is empty or too long", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn update_todo(
+    State(store): State<TodoStore>,
+    State(events): State<Events>,
+    AppPath(id): AppPath<u64>,
+    AppJson(mut changes): AppJson<UpdateTodo>,
+) -> Result<AppJson<Todo>, Problem> {
+    let mut errors = Vec::new();
+    changes.title = changes.title.map(|title| validate_title(&title, &mut errors));
+    if !errors.is_empty() {
+        return Err(Problem::invalid(errors));
+    }
+
+    let todo = store.update(id, changes).await?.ok_or_else(|| not_found(id))?;
+    events.publish(Event::TodoUpdated { todo: todo.clone() });
+    Ok(AppJson(todo))
+}
+
+/// Delete a todo
+///
+/// Needs an admin.
+#[utoipa::path(
+    delete,
+    path = "/todos/{id}",
+    tag = "todos",
+    params(("id" = u64, Path, description = "The todo's id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 401, description = "Not logged in", body = Problem, content_type = PROBLEM),
+        (status = 403, description = "Not an admin", body = Problem, content_type = PROBLEM),
+        (status = 404, description = "There's no todo with that id", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn delete_todo(
+    State(store): State<TodoStore>,
+    State(events): State<Events>,
+    AppPath(id): AppPath<u64>,
+) -> Result<StatusCode, Problem> {
+    if !store.delete(id).await? {
+        return Err(not_found(id));
+    }
+    events.publish(Event::TodoDeleted { id });
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn not_found(id: u64) -> Problem {
+    Problem::not_found(format!("There is no todo with id {}", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{CurrentUser, Role};
+    use crate::{AppState, app};
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{HeaderMap, Method, Request},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    /// The whole app on a fresh in-memory SQLite database, and an admin's token for it.
+    async fn test_app() -> (Router, String) {
+        let state = AppState::for_tests().await;
+        let admin = CurrentUser { id: 1, username: "admin".to_string(), role: Role::Admin };
+        let (token, _) = state.keys.issue(&admin).unwrap();
+        (app(state), token)
+    }
+
+    /// Sends one request through the whole app, as the server would.
+    async fn send(app: &Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, HeaderMap, Value) {
+        send_as(app, None, method, uri, body).await
+    }
+
+    async fn send_as(
+        app: &Router,
+        token: Option<&str>,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+
+        let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = to_bytes(body, usize::MAX).await.unwrap();
+        let json = if bytes.is_empty() { Value::Null } else { serde_json::from_slice(&bytes).unwrap() };
+        (parts.status, parts.headers, json)
+    }
+
+    #[tokio::test]
+    async fn create_get_update_delete() {
+        let (app, admin) = test_app().await;
+        let admin = Some(admin.as_str());
+
+        let (status, headers, created) =
+            send_as(&app, admin, Method::POST, "/todos", Some(json!({ "title": "  Buy milk " }))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created, json!({ "id": 1, "title": "Buy milk", "completed": false }));
+        assert_eq!(headers[header::LOCATION], "/todos/1");
+
+        let (status, _, fetched) = send(&app, Method::GET, "/todos/1", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(fetched, created);
+
+        let (status, _, updated) =
+            send_as(&app, admin, Method::PATCH, "/todos/1", Some(json!({ "completed": true }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated, json!({ "id": 1, "title": "Buy milk", "completed": true }));
+
+        let (status, _, _) = send_as(&app, admin, Method::DELETE, "/todos/1", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, headers, problem) = send(&app, Method::GET, "/todos/1", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+        assert_eq!(problem["status"], 404);
+    }
+
+    #[tokio::test]
+    async fn lists_in_pages() {
+        let (app, admin) = test_app().await;
+        let admin = Some(admin.as_str());
+        for n in 1..=5 {
+            send_as(&app, admin, Method::POST, "/todos", Some(json!({ "title": format!("todo {}", n) }))).await;
+        }
+
+        let (status, _, page) = send(&app, Method::GET, "/todos?page=2&per_page=2", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: Page<Todo> = serde_json::from_value(page).unwrap();
+        let titles: Vec<&str> = page.items.iter().map(|todo| todo.title.as_str()).collect();
+        assert_eq!(titles, vec!["todo 3", "todo 4"]);
+        assert_eq!((page.page, page.per_page, page.total), (2, 2, 5));
+
+        let (_, _, page) = send(&app, Method::GET, "/todos?page=9", None).await;
+        assert_eq!(page["items"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_get_problems() {
+        let (app, admin) = test_app().await;
+        let admin = Some(admin.as_str());
+
+        let (status, _, problem) = send_as(&app, admin, Method::POST, "/todos", Some(json!({ "title": " " }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["errors"], json!([{ "field": "title", "message": "must not be empty" }]));
+
+        let (status, _, problem) = send(&app, Method::GET, "/todos?page=0&per_page=1000", None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["errors"].as_array().unwrap().len(), 2);
+
+        // Rejections from the extractors themselves are problems too.
+        let (status, headers, problem) =
+            send_as(&app, admin, Method::POST, "/todos", Some(json!({ "titel": "typo" }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+        assert!(problem["detail"].as_str().unwrap().contains("titel"));
+
+        let (status, _, _) = send(&app, Method::GET, "/todos/abc", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, _) = send_as(&app, admin, Method::PATCH, "/todos/7", Some(json!({ "title": "nope" }))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
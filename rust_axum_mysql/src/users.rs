@@ -0,0 +1,310 @@
+use crate::auth::{CurrentUser, Keys, Role};
+use crate::extract::AppJson;
+use crate::problem::{FieldError, PROBLEM, Problem};
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+use axum::{
+    extract::{FromRef, State},
+    http::StatusCode,
+};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use sqlx::{AnyPool, Row};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+const MIN_PASSWORD: usize = 8;
+
+/**
+    A hash of a password nobody has, made with Argon2::default()'s settings.
+
+    Why check passwords against it?
+        Looking up a username that doesn't exist is quick, while checking a
+        password takes the better part of a hash on purpose. If logging in
+        as someone unknown skipped that, how long the 401 takes would tell
+        which usernames exist. Checking against this instead costs the same.
+*/
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$mS/b05BhnGBlPLCZeB8nUg$/aI24ML4t+WXRtuSbSxHH1WnP1Vvi8BZK5XamAPRdt8";
+
+/**
+    Accounts and logging in:
+
+        POST /auth/register   {"username", "password"}, 201 with the new user
+        POST /auth/login      {"username", "password"}, 200 with a bearer token
+        GET  /auth/me         the user the token belongs to
+
+    Everyone registers as a "user". An admin is made in the database:
+
+        UPDATE users SET role = 'admin' WHERE username = 'alice';
+
+    and gets the new role with their next login, since a token carries the
+    role it was issued with.
+*/
+pub fn router<S>() -> OpenApiRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+    UserStore: FromRef<S>,
+    Arc<Keys>: FromRef<S>,
+{
+    OpenApiRouter::new().routes(routes!(register)).routes(routes!(login)).routes(routes!(me))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Token {
+    pub access_token: String,
+    pub token_type: String,
+    /// Seconds until it expires.
+    pub expires_in: u64,
+}
+
+/**
+    The `users` table.
+
+    Why argon2?
+        Passwords are stored as slow, salted hashes so that a leaked table
+        doesn't leak the passwords: each guess costs an attacker as much
+        work as a login costs us. The hash string carries its own salt and
+        parameters ("$argon2id$v=19$m=19456,t=2,p=1$salt$hash"), so
+        checking needs nothing else.
+
+    Hashing takes tens of milliseconds of CPU on purpose, so it runs in
+    spawn_blocking instead of holding up the async worker threads.
+*/
+#[derive(Clone)]
+pub struct UserStore {
+    pool: AnyPool,
+}
+
+impl UserStore {
+    pub fn new(pool: AnyPool) -> UserStore {
+        UserStore { pool }
+    }
+
+    /// `None` if the username is taken.
+    pub async fn create(&self, username: &str, password: &str, role: Role) -> Result<Option<CurrentUser>, Problem> {
+        let password = password.to_string();
+        let hash = tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default().hash_password(password.as_bytes(), &salt).map(|hash| hash.to_string())
+        })
+        .await
+        .map_err(|e| hashing_failed(&e))?
+        .map_err(|e| hashing_failed(&e))?;
+
+        let mut connection = self.pool.acquire().await?;
+        let result = sqlx::query("INSERT INTO users (username, password_hash, role) VALUES (?, ?, ?)")
+            .bind(username)
+            .bind(hash)
+            .bind(role.as_str())
+            .execute(&mut *connection)
+            .await;
+        let result = match result {
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return Ok(None),
+            result => result?,
+        };
+        // The same dance as TodoStore::create for the new id.
+        let id = match result.last_insert_id() {
+            Some(id) => id,
+            None => sqlx::query_scalar("SELECT last_insert_rowid()").fetch_one(&mut *connection).await?,
+        };
+        Ok(Some(CurrentUser { id: id as u64, username: username.to_string(), role }))
+    }
+
+    /// The user, if the password is theirs.
+    pub async fn check(&self, username: &str, password: &str) -> Result<Option<CurrentUser>, Problem> {
+        let row = sqlx::query("SELECT id, password_hash, role FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
+        let (account, hash) = match row {
+            Some(row) => {
+                let id: i64 = row.try_get("id")?;
+                let role: String = row.try_get("role")?;
+                (Some((id, role)), row.try_get("password_hash")?)
+            }
+            None => (None, DUMMY_HASH.to_string()),
+        };
+
+        let password = password.to_string();
+        let matches = tokio::task::spawn_blocking(move || {
+            let hash = PasswordHash::new(&hash);
+            hash.is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        })
+        .await
+        .map_err(|e| hashing_failed(&e))?;
+        let Some((id, role)) = account.filter(|_| matches) else {
+            return Ok(None);
+        };
+
+        let role = Role::parse(&role).ok_or_else(|| {
+            tracing::error!("User {} has an unknown role {:?}", username, role);
+            Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "The account is misconfigured")
+        })?;
+        Ok(Some(CurrentUser { id: id as u64, username: username.to_string(), role }))
+    }
+}
+
+fn hashing_failed(error: &dyn std::fmt::Display) -> Problem {
+    tracing::error!("Password hashing failed: {}", error);
+    Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "Could not process the password")
+}
+
+fn validate(credentials: &Credentials) -> Result<(), Problem> {
+    let mut errors = Vec::new();
+    let username = &credentials.username;
+    if !(3..=32).contains(&username.len()) {
+        errors.push(FieldError { field: "username", message: "must be 3 to 32 characters".to_string() });
+    } else if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+        errors.push(FieldError { field: "username", message: "may only use letters, digits, _, - and .".to_string() });
+    }
+    if credentials.password.chars().count() < MIN_PASSWORD {
+        errors.push(FieldError { field: "password", message: format!("must be at least {} characters", MIN_PASSWORD) });
+    }
+    if errors.is_empty() { Ok(()) } else { Err(Problem::invalid(errors)) }
+}
+
+/// Register
+///
+/// Usernames are 3 to 32 letters, digits, _, - or ., passwords at least 8 characters.
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    request_body = Credentials,
+    responses(
+        (status = 201, description = "The new user", body = CurrentUser),
+        (status = 409, description = "The username is taken", body = Problem, content_type = PROBLEM),
+        (status = 422, description = "The username or password isn't allowed", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn register(
+    State(users): State<UserStore>,
+    AppJson(credentials): AppJson<Credentials>,
+) -> Result<(StatusCode, AppJson<CurrentUser>), Problem> {
+    validate(&credentials)?;
+    let user = users
+        .create(&credentials.username, &credentials.password, Role::User)
+        .await?
+        .ok_or_else(|| Problem::conflict(format!("The username {} is taken", credentials.username)))?;
+    Ok((StatusCode::CREATED, AppJson(user)))
+}
+
+/// Log in
+///
+/// The token goes in the Authorization header of later requests: `Bearer <access_token>`.
+///
+/// The same answer for an unknown user and a wrong password, so logging in can't be used to find usernames.
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = Credentials,
+    responses(
+        (status = 200, description = "A bearer token", body = Token),
+        (status = 401, description = "Wrong username or password", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn login(
+    State(users): State<UserStore>,
+    State(keys): State<Arc<Keys>>,
+    AppJson(credentials): AppJson<Credentials>,
+) -> Result<AppJson<Token>, Problem> {
+    let user = users
+        .check(&credentials.username, &credentials.password)
+        .await?
+        .ok_or_else(|| Problem::unauthorized("Wrong username or password"))?;
+    let (access_token, expires_in) = keys.issue(&user)?;
+    Ok(AppJson(Token { access_token, token_type: "Bearer".to_string(), expires_in }))
+}
+
+/// Who am I
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The user the token belongs to", body = CurrentUser),
+        (status = 401, description = "No token, or not a valid one", body = Problem, content_type = PROBLEM),
+    ),
+)]
+async fn me(user: CurrentUser) -> AppJson<CurrentUser> {
+    AppJson(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppState, app};
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, StatusCode, header},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    async fn post(app: &axum::Router, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn the_dummy_hash_costs_as_much_as_a_real_one() {
+        let dummy = PasswordHash::new(DUMMY_HASH).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        let real = Argon2::default().hash_password(b"correct horse", &salt).unwrap();
+        assert_eq!((dummy.algorithm, dummy.version, dummy.params), (real.algorithm, real.version, real.params));
+    }
+
+    #[tokio::test]
+    async fn register_log_in_and_ask_who_i_am() {
+        let app = app(AppState::for_tests().await);
+        let alice = json!({ "username": "alice", "password": "correct horse" });
+
+        let (status, user) = post(&app, "/auth/register", alice.clone()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(user, json!({ "id": 1, "username": "alice", "role": "user" }));
+        let (status, _) = post(&app, "/auth/register", alice.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, problem) = post(&app, "/auth/register", json!({ "username": "b!", "password": "short" })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["errors"].as_array().unwrap().len(), 2);
+
+        let (status, token) = post(&app, "/auth/login", alice).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(token["token_type"], "Bearer");
+
+        let me = Request::get("/auth/me")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token["access_token"].as_str().unwrap()))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(me).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap(), user);
+
+        let wrong_password = json!({ "username": "alice", "password": "wrong horse" });
+        let unknown_user = json!({ "username": "bob", "password": "whatever1" });
+        for wrong in [wrong_password, unknown_user] {
+            let (status, problem) = post(&app, "/auth/login", wrong).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(problem["detail"], "Wrong username or password");
+        }
+    }
+}
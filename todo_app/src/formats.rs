@@ -0,0 +1,340 @@
+mod csv;
+mod ics;
+mod markdown;
+mod todotxt;
+
+use crate::id::new_id;
+use crate::timelog::Session;
+use crate::todo::Todo;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Timelike, Utc};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use ulid::Ulid;
+
+/**
+    File formats for `todo export` and `todo import`.
+
+    Each one writes the standard fields of its format where there is one
+    (todo.txt priorities and contexts, iCalendar DUE and CATEGORIES, ...) so
+    other tools can read the file, and keeps whatever the standard can't
+    express in extra keys, so that importing an export gives back exactly
+    the same todos.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    TodoTxt,
+    Markdown,
+    Ics,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s.to_lowercase().as_str() {
+            "todotxt" | "txt" => Ok(Format::TodoTxt),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "ics" | "ical" => Ok(Format::Ics),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("Unknown format: {} (expected todotxt, markdown, ics or csv)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::TodoTxt => write!(f, "todotxt"),
+            Format::Markdown => write!(f, "markdown"),
+            Format::Ics => write!(f, "ics"),
+            Format::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+/// Where in an imported file something went wrong.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+impl Format {
+    /// Guesses the format of a file from its extension, or failing that
+    /// from its contents. Anything unrecognised is read as todo.txt.
+    pub fn detect(path: &Path, contents: &str) -> Format {
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("txt") => return Format::TodoTxt,
+            Some("md") | Some("markdown") => return Format::Markdown,
+            Some("ics") | Some("ical") => return Format::Ics,
+            Some("csv") => return Format::Csv,
+            _ => {}
+        }
+
+        let first = contents.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+        if first.eq_ignore_ascii_case("BEGIN:VCALENDAR") {
+            Format::Ics
+        } else if contents.lines().any(|l| markdown::item(l).is_some()) {
+            Format::Markdown
+        } else if first.split(',').any(|column| column.trim().eq_ignore_ascii_case("text")) {
+            Format::Csv
+        } else {
+            Format::TodoTxt
+        }
+    }
+
+    pub fn export(self, todos: &[Todo]) -> String {
+        match self {
+            Format::TodoTxt => todotxt::export(todos),
+            Format::Markdown => markdown::export(todos),
+            Format::Ics => ics::export(todos),
+            Format::Csv => csv::export(todos),
+        }
+    }
+
+    /// Reads todos from `contents`. Todos without a valid ID, such as ones
+    /// written by other tools, get a new one, and links to them within the
+    /// file are updated to match.
+    pub fn import(self, contents: &str) -> Result<Vec<Todo>, ParseError> {
+        let mut todos = match self {
+            Format::TodoTxt => todotxt::import(contents)?,
+            Format::Markdown => markdown::import(contents)?,
+            Format::Ics => ics::import(contents)?,
+            Format::Csv => csv::import(contents)?,
+        };
+
+        let mut renamed = HashMap::new();
+        for todo in todos.iter_mut() {
+            let id = match Ulid::from_string(&todo.id.to_uppercase()) {
+                Ok(_) => todo.id.to_lowercase(),
+                Err(_) => new_id(),
+            };
+            renamed.insert(std::mem::replace(&mut todo.id, id.clone()), id);
+        }
+
+        let rename = |id: &mut String| {
+            if let Some(new) = renamed.get(id.as_str()) {
+                id.clone_from(new);
+            }
+        };
+        for todo in todos.iter_mut() {
+            todo.parent.iter_mut().for_each(rename);
+            todo.blocked_by.iter_mut().for_each(rename);
+        }
+        Ok(todos)
+    }
+}
+
+/// A todo with no ID and nothing set, for importers to fill in. The
+/// creation time stays "now" unless the file says otherwise.
+fn blank() -> Todo {
+    let mut todo = Todo::new("");
+    todo.id.clear();
+    todo
+}
+
+/// RFC 3339 with as many fractional digits as the time has, so nothing is lost.
+fn timestamp(t: &DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn parse_timestamp(s: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid timestamp: {}", s))
+}
+
+/// A due date as `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM` or with seconds, whichever
+/// is the shortest that keeps it exact.
+fn local_time(t: &NaiveDateTime) -> String {
+    let format = if t.time() == NaiveTime::MIN {
+        "%Y-%m-%d"
+    } else if t.second() == 0 && t.nanosecond() == 0 {
+        "%Y-%m-%dT%H:%M"
+    } else {
+        "%Y-%m-%dT%H:%M:%S%.f"
+    };
+    t.format(format).to_string()
+}
+
+fn parse_local_time(s: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M"))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN)))
+        .map_err(|_| format!("Invalid date: {}", s))
+}
+
+/// Work sessions as ISO 8601 intervals, `start/end`, separated by commas.
+/// A running session has nothing after the slash.
+fn sessions(sessions: &[Session]) -> String {
+    let intervals: Vec<String> = sessions
+        .iter()
+        .map(|s| format!("{}/{}", timestamp(&s.start), s.end.as_ref().map(timestamp).unwrap_or_default()))
+        .collect();
+    intervals.join(",")
+}
+
+fn parse_sessions(s: &str) -> Result<Vec<Session>, String> {
+    s.split(',')
+        .filter(|interval| !interval.is_empty())
+        .map(|interval| {
+            let (start, end) = interval.split_once('/').ok_or_else(|| format!("Invalid session: {}", interval))?;
+            let end = if end.is_empty() { None } else { Some(parse_timestamp(end)?) };
+            Ok(Session { start: parse_timestamp(start)?, end })
+        })
+        .collect()
+}
+
+/// Backslash-escapes `\`, line breaks and any of `special`, so the result
+/// fits on one line and can be split on the special characters.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c if special.contains(&c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> String {
+    split_escaped(s, None).remove(0)
+}
+
+/// Undoes `escape`, splitting on every unescaped `sep`.
+fn split_escaped(s: &str, sep: Option<char>) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("starts with one part");
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => part.push('\n'),
+                Some('r') => part.push('\r'),
+                Some(other) => part.push(other),
+                None => part.push('\\'),
+            },
+            c if Some(c) == sep => parts.push(String::new()),
+            c => part.push(c),
+        }
+    }
+    parts
+}
+
+/// A list written with `escape`, one item per `sep`; empty means no items.
+fn split_list(s: &str, sep: char) -> Vec<String> {
+    if s.is_empty() {
+        Vec::new()
+    } else {
+        split_escaped(s, Some(sep))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::{parse_due, Priority};
+
+    /// One todo with every field set to something awkward, and a few plain ones.
+    fn sample() -> Vec<Todo> {
+        let mut full = Todo::new("Fix +bug @home due:now  in C:\\temp, \"quoted\"; 50% off %41 \\n");
+        full.priority = Some(Priority::High);
+        full.due = Some(parse_due("2026-10-23 10:00").unwrap());
+        full.remind_at = Some(NaiveDateTime::parse_from_str("2026-10-22 18:30:05", "%Y-%m-%d %H:%M:%S").unwrap());
+        full.tags = vec!["work".into(), "two words".into(), "a,b".into()];
+        full.project = Some("acme inc".into());
+        full.notes = Some("First line\nsecond: with +plus\n\ttabbed\n".into());
+        full.recur = Some("weekly:mon,thu".parse().unwrap());
+        full.sessions = vec![
+            Session {
+                start: parse_timestamp("2026-10-19T09:00:00Z").unwrap(),
+                end: Some(parse_timestamp("2026-10-19T09:45:10Z").unwrap()),
+            },
+            Session { start: parse_timestamp("2026-10-19T10:00:00.5Z").unwrap(), end: None },
+        ];
+        full.complete();
+
+        let mut plain = Todo::new("Buy milk");
+        plain.sessions = vec![Session { start: Utc::now(), end: None }];
+
+        let mut trimmed = Todo::new(" 2026-01-01 (A) x  leading and trailing ");
+        trimmed.recur = Some("after:3".parse().unwrap());
+        trimmed.due = Some(parse_due("2026-11-01").unwrap());
+        trimmed.priority = Some(Priority::Low);
+
+        let mut done_without_time = Todo::new("Legacy done");
+        done_without_time.done = true;
+        done_without_time.notes = Some(String::new());
+
+        let mut monthly = Todo::new("Pay rent");
+        monthly.recur = Some("monthly:31".parse().unwrap());
+        monthly.due = Some(NaiveDateTime::parse_from_str("2026-10-31 09:15:30", "%Y-%m-%d %H:%M:%S").unwrap());
+        monthly.priority = Some(Priority::Medium);
+
+        let mut todos = vec![full, plain, trimmed, done_without_time, monthly];
+        todos[0].parent = Some(todos[4].id.clone());
+        todos[1].parent = Some(todos[0].id.clone());
+        todos[1].blocked_by = vec![todos[2].id.clone(), todos[3].id.clone()];
+        todos
+    }
+
+    #[test]
+    fn export_then_import_is_lossless() {
+        let todos = sample();
+        for format in [Format::TodoTxt, Format::Markdown, Format::Ics, Format::Csv] {
+            let exported = format.export(&todos);
+            let imported = format.import(&exported).unwrap_or_else(|e| panic!("{}: {}\n{}", format, e, exported));
+            assert_eq!(imported, todos, "{} round trip:\n{}", format, exported);
+        }
+    }
+
+    #[test]
+    fn detects_format_from_extension_then_contents() {
+        let todos = sample();
+        assert_eq!(Format::detect(Path::new("x.ICS"), ""), Format::Ics);
+        assert_eq!(Format::detect(Path::new("todo.txt"), "- [ ] looks like markdown"), Format::TodoTxt);
+
+        for format in [Format::TodoTxt, Format::Markdown, Format::Ics, Format::Csv] {
+            assert_eq!(Format::detect(Path::new("export"), &format.export(&todos)), format);
+        }
+    }
+
+    #[test]
+    fn foreign_ids_are_replaced() {
+        let todos = Format::TodoTxt.import("Call mom id:not-a-ulid\nCall dad parent:not-a-ulid\n").unwrap();
+        assert_eq!(todos.len(), 2);
+        assert!(todos.iter().all(|todo| Ulid::from_string(&todo.id.to_uppercase()).is_ok()));
+        assert_ne!(todos[0].id, todos[1].id);
+        assert_eq!(todos[1].parent.as_ref(), Some(&todos[0].id));
+    }
+
+    #[test]
+    fn escaping_round_trips() {
+        for s in ["", "plain", "back\\slash", "new\nline\r", "a,b\\,c", "trailing\\"] {
+            assert_eq!(unescape(&escape(s, &[','])), s);
+        }
+        assert_eq!(split_list(&escape("a,b", &[',']), ','), vec!["a,b"]);
+        assert_eq!(split_list("a,b", ','), vec!["a", "b"]);
+        assert!(split_list("", ',').is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+use super::{
+    blank, escape, local_time, parse_local_time, parse_sessions, parse_timestamp, sessions, split_list, timestamp,
+    unescape, ParseError,
+};
+use crate::todo::Todo;
+
+const COLUMNS: [&str; 15] = [
+    "id",
+    "text",
+    "done",
+    "priority",
+    "due",
+    "every",
+    "project",
+    "tags",
+    "created",
+    "completed",
+    "notes",
+    "parent",
+    "blocked_by",
+    "time",
+    "remind",
+];
+
+/**
+    Comma-separated values (RFC 4180) with a header row, for spreadsheets.
+
+    Empty cells are unset fields. Tags share one cell, separated by commas,
+    with any comma inside a tag backslash-escaped, as are backslashes in
+    notes and the other free-text cells so that an empty note can be told
+    apart from none.
+*/
+pub fn export(todos: &[Todo]) -> String {
+    let mut out = String::new();
+    push_record(&mut out, COLUMNS.iter().map(|c| c.to_string()));
+
+    for todo in todos {
+        let tags: Vec<String> = todo.tags.iter().map(|tag| escape(tag, &[','])).collect();
+        push_record(
+            &mut out,
+            [
+                todo.id.clone(),
+                todo.text.clone(),
+                todo.done.to_string(),
+                todo.priority.map(|p| p.to_string()).unwrap_or_default(),
+                todo.due.as_ref().map(local_time).unwrap_or_default(),
+                todo.recur.as_ref().map(|r| r.to_string()).unwrap_or_default(),
+                optional(&todo.project),
+                tags.join(","),
+                timestamp(&todo.created_at),
+                todo.completed_at.as_ref().map(timestamp).unwrap_or_default(),
+                optional(&todo.notes),
+                todo.parent.clone().unwrap_or_default(),
+                todo.blocked_by.join(","),
+                sessions(&todo.sessions),
+                todo.remind_at.as_ref().map(local_time).unwrap_or_default(),
+            ],
+        );
+    }
+    out
+}
+
+/// An optional string cell: empty when unset, and `\` alone when set but
+/// empty.
+fn optional(value: &Option<String>) -> String {
+    match value.as_deref() {
+        None => String::new(),
+        Some("") => "\\".to_string(),
+        Some(value) => escape(value, &[]),
+    }
+}
+
+fn parse_optional(cell: &str) -> Option<String> {
+    match cell {
+        "" => None,
+        "\\" => Some(String::new()),
+        cell => Some(unescape(cell)),
+    }
+}
+
+fn push_record(out: &mut String, cells: impl IntoIterator<Item = String>) {
+    let cells: Vec<String> = cells
+        .into_iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell
+            }
+        })
+        .collect();
+    out.push_str(&cells.join(","));
+    out.push_str("\r\n");
+}
+
+/// Splits `contents` into records of cells, each with the line it starts on.
+/// Quoted cells may contain commas, doubled quotes and line breaks.
+fn records(contents: &str) -> Result<Vec<(usize, Vec<String>)>, ParseError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.next_if_eq(&'"').is_some() => cell.push('"'),
+            ('"', true) => quoted = false,
+            ('"', false) if cell.is_empty() => quoted = true,
+            (',', false) => record.push(std::mem::take(&mut cell)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut cell));
+                records.push((start, std::mem::take(&mut record)));
+                line += 1;
+                start = line;
+            }
+            (c, _) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                cell.push(c);
+            }
+        }
+    }
+
+    if quoted {
+        return Err(ParseError { line: start, message: "Unterminated quoted cell".to_string() });
+    }
+    if !cell.is_empty() || !record.is_empty() {
+        record.push(cell);
+        records.push((start, record));
+    }
+
+    // Blank lines carry no todo.
+    records.retain(|(_, cells)| !(cells.len() == 1 && cells[0].is_empty()));
+    Ok(records)
+}
+
+pub fn import(contents: &str) -> Result<Vec<Todo>, ParseError> {
+    let mut records = records(contents)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+
+    // Columns are found by name, so they can be in any order and extra ones are ignored.
+    let header: Vec<String> = header.iter().map(|name| name.trim().to_lowercase()).collect();
+    if !header.iter().any(|name| name == "text") {
+        return Err(ParseError { line: 1, message: "Missing a \"text\" column".to_string() });
+    }
+
+    let mut todos = Vec::new();
+    for (line, cells) in records {
+        let error = |message: String| ParseError { line, message };
+        let mut todo = blank();
+
+        for (name, cell) in header.iter().zip(&cells) {
+            match name.as_str() {
+                "id" => todo.id = cell.clone(),
+                "text" => todo.text = cell.clone(),
+                "done" => todo.done = matches!(cell.to_lowercase().as_str(), "true" | "yes" | "x" | "1"),
+                _ if cell.is_empty() => {}
+                "priority" => todo.priority = Some(cell.parse().map_err(error)?),
+                "due" => todo.due = Some(parse_local_time(cell).map_err(error)?),
+                "every" => todo.recur = Some(cell.parse().map_err(error)?),
+                "project" => todo.project = parse_optional(cell),
+                "tags" => todo.tags = split_list(cell, ','),
+                "created" => todo.created_at = parse_timestamp(cell).map_err(error)?,
+                "completed" => todo.completed_at = Some(parse_timestamp(cell).map_err(error)?),
+                "notes" => todo.notes = parse_optional(cell),
+                "parent" => todo.parent = Some(cell.clone()),
+                "blocked_by" => todo.blocked_by = cell.split(',').map(String::from).collect(),
+                "remind" => todo.remind_at = Some(parse_local_time(cell).map_err(error)?),
+                "time" => todo.sessions = parse_sessions(cell).map_err(error)?,
+                _ => {}
+            }
+        }
+        todos.push(todo);
+    }
+
+    Ok(todos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_quoted_cells() {
+        let records = records("a,\"b,c\",\"say \"\"hi\"\"\"\r\n\r\n\"multi\nline\",x\n").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                (1, vec!["a".to_string(), "b,c".to_string(), "say \"hi\"".to_string()]),
+                (3, vec!["multi\nline".to_string(), "x".to_string()]),
+            ]
+        );
+        assert!(super::records("\"open").is_err());
+    }
+
+    #[test]
+    fn reads_columns_by_name() {
+        let todos = import("Priority,Text,Extra\nhigh,Call mom,ignored\n,Buy milk,\n").unwrap();
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].text, "Call mom");
+        assert_eq!(todos[0].priority, Some(crate::todo::Priority::High));
+        assert_eq!(todos[1].priority, None);
+
+        assert_eq!(import("name\nCall mom\n").unwrap_err().line, 1);
+        assert_eq!(import("text,due\nok,\nbad,someday\n").unwrap_err().line, 3);
+    }
+}
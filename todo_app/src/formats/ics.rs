@@ -0,0 +1,334 @@
+use super::{
+    blank, escape, local_time, parse_local_time, parse_sessions, parse_timestamp, sessions, split_list, timestamp,
+    unescape, ParseError,
+};
+use crate::recur::Recurrence;
+use crate::todo::{Priority, Todo};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+
+/// Longest line RFC 5545 allows before it has to be folded, in bytes.
+const MAX_LINE: usize = 75;
+
+const WEEKDAYS: [(Weekday, &str); 7] = [
+    (Weekday::Mon, "MO"),
+    (Weekday::Tue, "TU"),
+    (Weekday::Wed, "WE"),
+    (Weekday::Thu, "TH"),
+    (Weekday::Fri, "FR"),
+    (Weekday::Sat, "SA"),
+    (Weekday::Sun, "SU"),
+];
+
+/**
+    An iCalendar file (RFC 5545) with one VTODO per todo.
+
+    Calendar apps see the summary, status, priority, due date, categories,
+    subtasks and dependencies (RELATED-TO, with the RELTYPE of RFC 9253)
+    and, where the rule has an RRULE equivalent, the recurrence. The project,
+    the exact recurrence rule, the reminder, tracked time and sub-second
+    timestamps go in `X-TODO-*` properties, which other apps keep but ignore.
+*/
+pub fn export(todos: &[Todo]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//todo_app//todo_app//EN".to_string(),
+    ];
+
+    for todo in todos {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}", todo.id));
+        lines.push(format!("DTSTAMP:{}", utc(&todo.created_at)));
+        lines.push(format!("CREATED:{}", utc(&todo.created_at)));
+        if todo.created_at.timestamp_subsec_nanos() != 0 {
+            lines.push(format!("X-TODO-CREATED:{}", timestamp(&todo.created_at)));
+        }
+        lines.push(format!("SUMMARY:{}", escape(&todo.text, &[';', ','])));
+        lines.push(format!("STATUS:{}", if todo.done { "COMPLETED" } else { "NEEDS-ACTION" }));
+        if let Some(completed) = &todo.completed_at {
+            lines.push(format!("COMPLETED:{}", utc(completed)));
+            if completed.timestamp_subsec_nanos() != 0 {
+                lines.push(format!("X-TODO-COMPLETED:{}", timestamp(completed)));
+            }
+        }
+        if let Some(priority) = todo.priority {
+            let level = match priority {
+                Priority::High => 1,
+                Priority::Medium => 5,
+                Priority::Low => 9,
+            };
+            lines.push(format!("PRIORITY:{}", level));
+        }
+        if let Some(due) = &todo.due {
+            lines.push(format!("DUE:{}", due.format("%Y%m%dT%H%M%S")));
+        }
+        if let Some(remind_at) = &todo.remind_at {
+            lines.push(format!("X-TODO-REMIND:{}", local_time(remind_at)));
+        }
+        if let Some(recur) = &todo.recur {
+            if let Some(rule) = rrule(recur) {
+                lines.push(format!("RRULE:{}", rule));
+            }
+            lines.push(format!("X-TODO-EVERY:{}", recur));
+        }
+        if let Some(parent) = &todo.parent {
+            lines.push(format!("RELATED-TO;RELTYPE=PARENT:{}", parent));
+        }
+        for blocker in &todo.blocked_by {
+            lines.push(format!("RELATED-TO;RELTYPE=DEPENDS-ON:{}", blocker));
+        }
+        if let Some(project) = &todo.project {
+            lines.push(format!("X-TODO-PROJECT:{}", escape(project, &[';', ','])));
+        }
+        if !todo.tags.is_empty() {
+            let tags: Vec<String> = todo.tags.iter().map(|tag| escape(tag, &[';', ','])).collect();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        if let Some(notes) = &todo.notes {
+            lines.push(format!("DESCRIPTION:{}", escape(notes, &[';', ','])));
+        }
+        if !todo.sessions.is_empty() {
+            lines.push(format!("X-TODO-TIME:{}", sessions(&todo.sessions)));
+        }
+        lines.push("END:VTODO".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(&fold(&line));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn utc(t: &DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn rrule(recur: &Recurrence) -> Option<String> {
+    match recur {
+        Recurrence::Daily => Some("FREQ=DAILY".to_string()),
+        Recurrence::Weekly { days } if days.is_empty() => Some("FREQ=WEEKLY".to_string()),
+        Recurrence::Weekly { days } => {
+            let days: Vec<&str> = days
+                .iter()
+                .filter_map(|day| WEEKDAYS.iter().find(|(d, _)| d == day).map(|(_, code)| *code))
+                .collect();
+            Some(format!("FREQ=WEEKLY;BYDAY={}", days.join(",")))
+        }
+        Recurrence::Monthly { day } => Some(format!("FREQ=MONTHLY;BYMONTHDAY={}", day)),
+        Recurrence::AfterCompletion { .. } => None,
+    }
+}
+
+fn parse_rrule(rule: &str) -> Result<Recurrence, String> {
+    let unsupported = || format!("Unsupported RRULE: {}", rule);
+    let parts: Vec<(&str, &str)> = rule.split(';').filter_map(|part| part.split_once('=')).collect();
+    let get = |name: &str| parts.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, v)| *v);
+
+    if get("INTERVAL").is_some_and(|interval| interval != "1") || get("COUNT").is_some() || get("UNTIL").is_some() {
+        return Err(unsupported());
+    }
+
+    match (get("FREQ"), get("BYDAY"), get("BYMONTHDAY")) {
+        (Some("DAILY"), None, None) => Ok(Recurrence::Daily),
+        (Some("WEEKLY"), Some(days), None) => {
+            let days = days
+                .split(',')
+                .map(|code| WEEKDAYS.iter().find(|(_, c)| *c == code).map(|(day, _)| *day))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(unsupported)?;
+            Ok(Recurrence::Weekly { days })
+        }
+        (Some("MONTHLY"), None, Some(day)) => match day.parse::<u32>() {
+            Ok(day @ 1..=31) => Ok(Recurrence::Monthly { day }),
+            _ => Err(unsupported()),
+        },
+        _ => Err(unsupported()),
+    }
+}
+
+/// Splits a line into 75-byte pieces joined by CRLF and a space, without
+/// breaking up a UTF-8 character.
+fn fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out
+}
+
+/// Joins folded lines back together, keeping the number of the line each
+/// one started on.
+fn unfold(contents: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (n, line) in contents.lines().enumerate() {
+        match (line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')), lines.last_mut()) {
+            (Some(continued), Some((_, last))) => last.push_str(continued),
+            _ => lines.push((n + 1, line.to_string())),
+        }
+    }
+    lines
+}
+
+pub fn import(contents: &str) -> Result<Vec<Todo>, ParseError> {
+    let mut todos = Vec::new();
+    // Properties of the VTODO being read: line number, name, parameters and value.
+    let mut current: Option<Vec<(usize, String, String, String)>> = None;
+    // Components nested inside a VTODO, like VALARM, whose properties aren't the todo's.
+    let mut nested = 0;
+
+    for (line, content) in unfold(contents) {
+        let Some((name, value)) = content.split_once(':') else {
+            continue;
+        };
+        let (name, params) = name.split_once(';').unwrap_or((name, ""));
+        let name = name.to_uppercase();
+
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VTODO") => current = Some(Vec::new()),
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(properties)) if value.eq_ignore_ascii_case("VTODO") => {
+                // Our own X-TODO-* properties refine the standard ones, so they go last.
+                properties.sort_by_key(|(_, name, _, _)| name.starts_with("X-TODO-"));
+
+                let mut todo = blank();
+                for (line, name, params, value) in properties.iter() {
+                    property(&mut todo, name, params, value).map_err(|message| ParseError { line: *line, message })?;
+                }
+                todos.push(todo);
+                current = None;
+            }
+            (_, Some(properties)) if nested == 0 => {
+                properties.push((line, name, params.to_string(), value.to_string()))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(todos)
+}
+
+/// Applies one property of a VTODO to `todo`.
+fn property(todo: &mut Todo, name: &str, params: &str, value: &str) -> Result<(), String> {
+    match name {
+        "UID" => todo.id = value.to_string(),
+        "SUMMARY" => todo.text = unescape(value),
+        "STATUS" => todo.done = value.eq_ignore_ascii_case("COMPLETED"),
+        "CREATED" => todo.created_at = parse_utc(value)?,
+        "X-TODO-CREATED" => todo.created_at = parse_timestamp(value)?,
+        "COMPLETED" => todo.completed_at = Some(parse_utc(value)?),
+        "X-TODO-COMPLETED" => todo.completed_at = Some(parse_timestamp(value)?),
+        "PRIORITY" => {
+            todo.priority = match value.trim().parse::<u8>() {
+                Ok(0) => None,
+                Ok(1..=4) => Some(Priority::High),
+                Ok(5) => Some(Priority::Medium),
+                Ok(6..=9) => Some(Priority::Low),
+                _ => return Err(format!("Invalid priority: {}", value)),
+            }
+        }
+        "DUE" => todo.due = Some(parse_due(value)?),
+        "RRULE" => todo.recur = Some(parse_rrule(value)?),
+        "X-TODO-EVERY" => todo.recur = Some(value.parse()?),
+        "X-TODO-REMIND" => todo.remind_at = Some(parse_local_time(value)?),
+        "X-TODO-TIME" => todo.sessions = parse_sessions(value)?,
+        "X-TODO-PROJECT" => todo.project = Some(unescape(value)),
+        "CATEGORIES" => todo.tags.extend(split_list(value, ',')),
+        "DESCRIPTION" => todo.notes = Some(unescape(value)),
+        "RELATED-TO" => {
+            let reltype = params
+                .split(';')
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.eq_ignore_ascii_case("RELTYPE"))
+                .map(|(_, reltype)| reltype.to_uppercase());
+            match reltype.as_deref().unwrap_or("PARENT") {
+                "PARENT" => todo.parent = Some(value.to_string()),
+                "DEPENDS-ON" => todo.blocked_by.push(value.to_string()),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_utc(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .map(|t| t.and_utc())
+        .map_err(|_| format!("Invalid date-time: {}", value))
+}
+
+/// Floating times are taken as they are, UTC ones are converted to local
+/// time, and a plain date means midnight.
+fn parse_due(value: &str) -> Result<NaiveDateTime, String> {
+    if value.ends_with('Z') {
+        return parse_utc(value).map(|t| t.with_timezone(&Local).naive_local());
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y%m%d").map(|d| d.and_time(NaiveTime::MIN)))
+        .map_err(|_| format!("Invalid due date: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_todos_from_other_calendars() {
+        let todos = import(
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:A meeting, not a todo\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VTODO\r\n\
+             UID:20261018T120000-42@example.com\r\n\
+             SUMMARY:Renew passport\\, soon\r\n\
+             DUE;VALUE=DATE:20261101\r\n\
+             PRIORITY:2\r\n\
+             CATEGORIES:admin,travel\r\n\
+             RRULE:FREQ=WEEKLY;BYDAY=MO,FR\r\n\
+             DESCRIPTION:Take two pho\r\n\
+             \x20tos\r\n\
+             BEGIN:VALARM\r\n\
+             DESCRIPTION:Reminder\r\n\
+             END:VALARM\r\n\
+             END:VTODO\r\n\
+             END:VCALENDAR\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(todos.len(), 1);
+        let todo = &todos[0];
+        assert_eq!(todo.text, "Renew passport, soon");
+        assert_eq!(todo.due.unwrap().to_string(), "2026-11-01 00:00:00");
+        assert_eq!(todo.priority, Some(Priority::High));
+        assert_eq!(todo.tags, vec!["admin", "travel"]);
+        assert_eq!(todo.recur, Some("weekly:mon,fri".parse().unwrap()));
+        assert_eq!(todo.notes.as_deref(), Some("Take two photos"));
+    }
+
+    #[test]
+    fn folds_long_lines_on_character_boundaries() {
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        let folded = fold(&line);
+        assert!(folded.split("\r\n").all(|part| part.len() <= MAX_LINE));
+        assert_eq!(unfold(&folded), vec![(1, line)]);
+    }
+
+    #[test]
+    fn rejects_rules_it_cannot_represent() {
+        assert!(parse_rrule("FREQ=DAILY;INTERVAL=2").is_err());
+        assert!(parse_rrule("FREQ=YEARLY").is_err());
+        assert_eq!(parse_rrule("FREQ=MONTHLY;BYMONTHDAY=15"), Ok(Recurrence::Monthly { day: 15 }));
+    }
+}
@@ -0,0 +1,157 @@
+use super::{
+    blank, escape, local_time, parse_local_time, parse_sessions, parse_timestamp, sessions, split_list, timestamp,
+    unescape, ParseError,
+};
+use crate::todo::Todo;
+
+/**
+    A GitHub-style task list, with every other field as a nested bullet:
+
+        - [ ] Send invoice
+          - id: 01k...
+          - due: 2026-10-23T10:00
+          - tags: billing, home
+
+    The text and values are backslash-escaped so each stays on one line.
+    Lines that aren't tasks or known fields are ignored on import, so a
+    checklist copied out of any Markdown document works too.
+*/
+pub fn export(todos: &[Todo]) -> String {
+    let mut out = String::from("# Todos\n\n");
+    for todo in todos {
+        let check = if todo.done { 'x' } else { ' ' };
+        out.push_str(&format!("- [{}] {}\n", check, escape(&todo.text, &[])));
+
+        let mut field = |key: &str, value: String| out.push_str(&format!("  - {}: {}\n", key, value));
+        field("id", todo.id.clone());
+        if let Some(parent) = &todo.parent {
+            field("parent", parent.clone());
+        }
+        if !todo.blocked_by.is_empty() {
+            field("blocked by", todo.blocked_by.join(", "));
+        }
+        if let Some(priority) = todo.priority {
+            field("priority", priority.to_string());
+        }
+        if let Some(due) = &todo.due {
+            field("due", local_time(due));
+        }
+        if let Some(remind_at) = &todo.remind_at {
+            field("remind", local_time(remind_at));
+        }
+        if let Some(recur) = &todo.recur {
+            field("every", recur.to_string());
+        }
+        if let Some(project) = &todo.project {
+            field("project", escape(project, &[]));
+        }
+        if !todo.tags.is_empty() {
+            let tags: Vec<String> = todo.tags.iter().map(|tag| escape(tag, &[','])).collect();
+            field("tags", tags.join(", "));
+        }
+        field("created", timestamp(&todo.created_at));
+        if let Some(completed) = &todo.completed_at {
+            field("completed", timestamp(completed));
+        }
+        if !todo.sessions.is_empty() {
+            field("time", sessions(&todo.sessions));
+        }
+        if let Some(notes) = &todo.notes {
+            field("notes", escape(notes, &[]));
+        }
+    }
+    out
+}
+
+/// The checkbox and text of a task line, if `line` is one.
+pub fn item(line: &str) -> Option<(bool, &str)> {
+    let rest = line.strip_prefix("- [").or_else(|| line.strip_prefix("* ["))?;
+    let (check, text) = rest.split_at_checked(3)?;
+    match check {
+        " ] " => Some((false, text)),
+        "x] " | "X] " => Some((true, text)),
+        _ => None,
+    }
+}
+
+pub fn import(contents: &str) -> Result<Vec<Todo>, ParseError> {
+    let mut todos: Vec<Todo> = Vec::new();
+
+    for (n, line) in contents.lines().enumerate() {
+        let error = |message: String| ParseError { line: n + 1, message };
+
+        if let Some((done, text)) = item(line) {
+            let mut todo = blank();
+            todo.done = done;
+            todo.text = unescape(text);
+            todos.push(todo);
+            continue;
+        }
+
+        let (Some(todo), Some(field)) = (todos.last_mut(), line.strip_prefix("  - ")) else {
+            continue;
+        };
+        let Some((key, value)) = field.split_once(':') else {
+            continue;
+        };
+        let value = value.strip_prefix(' ').unwrap_or(value);
+
+        match key {
+            "id" => todo.id = value.to_string(),
+            "parent" => todo.parent = Some(value.to_string()),
+            "blocked by" => todo.blocked_by = value.split(',').map(|id| id.trim().to_string()).collect(),
+            "priority" => todo.priority = Some(value.parse().map_err(error)?),
+            "due" => todo.due = Some(parse_local_time(value).map_err(error)?),
+            "remind" => todo.remind_at = Some(parse_local_time(value).map_err(error)?),
+            "every" => todo.recur = Some(value.parse().map_err(error)?),
+            "project" => todo.project = Some(unescape(value)),
+            "tags" => {
+                let tags = split_list(value, ',');
+                let tags = tags.into_iter().enumerate().map(|(i, tag)| match tag.strip_prefix(' ') {
+                    Some(tag) if i > 0 => tag.to_string(),
+                    _ => tag,
+                });
+                todo.tags.extend(tags);
+            }
+            "created" => todo.created_at = parse_timestamp(value).map_err(error)?,
+            "completed" => todo.completed_at = Some(parse_timestamp(value).map_err(error)?),
+            "time" => todo.sessions = parse_sessions(value).map_err(error)?,
+            "notes" => todo.notes = Some(unescape(value)),
+            _ => {}
+        }
+    }
+
+    Ok(todos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_any_checklist() {
+        let todos = import(
+            "# Groceries\n\
+             \n\
+             Some intro text.\n\
+             - [ ] Milk\n\
+             - [X] Bread\n\
+             \x20\x20- tags: food, shop\n\
+             \x20\x20- aisle: 4\n\
+             * [x] Eggs\n\
+             - not a task\n",
+        )
+        .unwrap();
+
+        let texts: Vec<&str> = todos.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["Milk", "Bread", "Eggs"]);
+        assert!(!todos[0].done && todos[1].done && todos[2].done);
+        assert_eq!(todos[1].tags, vec!["food", "shop"]);
+    }
+
+    #[test]
+    fn reports_bad_values_with_line_number() {
+        let err = import("- [ ] Milk\n  - priority: urgent\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
@@ -0,0 +1,317 @@
+use super::{blank, local_time, parse_local_time, parse_sessions, parse_timestamp, sessions, timestamp, ParseError};
+use crate::todo::{Priority, Todo};
+use chrono::{NaiveDate, NaiveTime};
+
+/// `key:value` pairs this format reads. Others are left in the text.
+const KEYS: [&str; 11] =
+    ["id", "due", "remind", "rec", "pri", "note", "created", "completed", "parent", "blocked", "time"];
+
+/**
+    One todo per line, as in todo.txt (https://github.com/todotxt/todo.txt):
+
+        (A) 2026-10-18 Send invoice +acme @billing due:2026-10-23T10:00 id:01k... created:2026-...
+
+    Tags are contexts. The date prefixes only hold days, so the exact times
+    go in `created:` and `completed:`, which win over the prefixes on import.
+    Whitespace in values, and anything in the text that would otherwise read
+    as a marker or key, is percent-encoded.
+*/
+pub fn export(todos: &[Todo]) -> String {
+    let mut out = String::new();
+    for todo in todos {
+        out.push_str(&line(todo));
+        out.push('\n');
+    }
+    out
+}
+
+fn line(todo: &Todo) -> String {
+    let mut parts = Vec::new();
+    if todo.done {
+        parts.push("x".to_string());
+        if let Some(completed) = &todo.completed_at {
+            parts.push(completed.format("%Y-%m-%d").to_string());
+        }
+    } else if let Some(priority) = todo.priority {
+        parts.push(format!("({})", letter(priority)));
+    }
+    parts.push(todo.created_at.format("%Y-%m-%d").to_string());
+    parts.push(description(&todo.text));
+
+    if let Some(project) = &todo.project {
+        parts.push(format!("+{}", encode(project)));
+    }
+    for tag in &todo.tags {
+        parts.push(format!("@{}", encode(tag)));
+    }
+    if let Some(due) = &todo.due {
+        parts.push(format!("due:{}", local_time(due)));
+    }
+    if let Some(remind_at) = &todo.remind_at {
+        parts.push(format!("remind:{}", local_time(remind_at)));
+    }
+    if let Some(recur) = &todo.recur {
+        parts.push(format!("rec:{}", recur));
+    }
+    if let Some(priority) = todo.priority.filter(|_| todo.done) {
+        parts.push(format!("pri:{}", letter(priority)));
+    }
+    parts.push(format!("id:{}", todo.id));
+    if let Some(parent) = &todo.parent {
+        parts.push(format!("parent:{}", parent));
+    }
+    if !todo.blocked_by.is_empty() {
+        parts.push(format!("blocked:{}", todo.blocked_by.join(",")));
+    }
+    parts.push(format!("created:{}", timestamp(&todo.created_at)));
+    if let Some(completed) = &todo.completed_at {
+        parts.push(format!("completed:{}", timestamp(completed)));
+    }
+    if !todo.sessions.is_empty() {
+        parts.push(format!("time:{}", sessions(&todo.sessions)));
+    }
+    if let Some(notes) = &todo.notes {
+        parts.push(format!("note:{}", encode(notes)));
+    }
+
+    parts.join(" ")
+}
+
+pub fn import(contents: &str) -> Result<Vec<Todo>, ParseError> {
+    let mut todos = Vec::new();
+    for (n, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        todos.push(parse_line(line).map_err(|message| ParseError { line: n + 1, message })?);
+    }
+    Ok(todos)
+}
+
+fn parse_line(line: &str) -> Result<Todo, String> {
+    let mut todo = blank();
+    let mut tokens = line.split(' ').peekable();
+
+    if tokens.next_if_eq(&"x").is_some() {
+        todo.done = true;
+    }
+    if let Some(priority) = tokens.peek().and_then(|t| priority_token(t)) {
+        todo.priority = Some(priority);
+        tokens.next();
+    }
+
+    // Completed tasks may have a completion date before the creation date.
+    let mut dates = Vec::new();
+    while dates.len() < if todo.done { 2 } else { 1 } {
+        match tokens.peek().and_then(|t| NaiveDate::parse_from_str(t, "%Y-%m-%d").ok()) {
+            Some(date) => dates.push(date),
+            None => break,
+        }
+        tokens.next();
+    }
+
+    let mut words = Vec::new();
+    let (mut created, mut completed) = (None, None);
+    for token in tokens {
+        if let Some(project) = token.strip_prefix('+').filter(|p| !p.is_empty() && todo.project.is_none()) {
+            todo.project = Some(decode(project));
+        } else if let Some(tag) = token.strip_prefix('@').filter(|t| !t.is_empty()) {
+            todo.tags.push(decode(tag));
+        } else if let Some((key, value)) = token.split_once(':').filter(|(key, _)| KEYS.contains(key)) {
+            match key {
+                "id" => todo.id = value.to_string(),
+                "due" => todo.due = Some(parse_local_time(value)?),
+                "remind" => todo.remind_at = Some(parse_local_time(value)?),
+                "rec" => todo.recur = Some(value.parse()?),
+                "pri" => {
+                    let priority = priority_letter(value).ok_or_else(|| format!("Invalid priority: {}", value))?;
+                    todo.priority = Some(priority);
+                }
+                "note" => todo.notes = Some(decode(value)),
+                "parent" => todo.parent = Some(value.to_string()),
+                "blocked" => todo.blocked_by = value.split(',').filter(|id| !id.is_empty()).map(String::from).collect(),
+                "time" => todo.sessions = parse_sessions(value)?,
+                "created" => created = Some(parse_timestamp(value)?),
+                _ => completed = Some(parse_timestamp(value)?),
+            }
+        } else {
+            words.push(token);
+        }
+    }
+    todo.text = decode(&words.join(" "));
+
+    if let Some(created) = created {
+        todo.created_at = created;
+        todo.completed_at = completed;
+    } else {
+        // A file from another tool: only the days are known.
+        let midnight = |date: &NaiveDate| date.and_time(NaiveTime::MIN).and_utc();
+        let (completion, creation) = if todo.done { (dates.first(), dates.get(1)) } else { (None, dates.first()) };
+        if let Some(creation) = creation {
+            todo.created_at = midnight(creation);
+        }
+        todo.completed_at = completed.or(completion.map(midnight));
+    }
+
+    Ok(todo)
+}
+
+fn letter(priority: Priority) -> char {
+    match priority {
+        Priority::High => 'A',
+        Priority::Medium => 'B',
+        Priority::Low => 'C',
+    }
+}
+
+/// todo.txt has 26 priorities; everything below B counts as low.
+fn priority_letter(s: &str) -> Option<Priority> {
+    match s {
+        "A" => Some(Priority::High),
+        "B" => Some(Priority::Medium),
+        s if s.len() == 1 && s.chars().all(|c| c.is_ascii_uppercase()) => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+fn priority_token(token: &str) -> Option<Priority> {
+    priority_letter(token.strip_prefix('(')?.strip_suffix(')')?)
+}
+
+/**
+    The text, encoded so that it reads back unchanged: single spaces stay
+    as they are, other whitespace is percent-encoded, and words that would
+    be taken for a project, context, key or leading date get their first
+    special character encoded.
+*/
+fn description(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut encoded = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let separator = c == ' ' && i > 0 && chars[i - 1] != ' ' && chars.get(i + 1).is_some_and(|&n| n != ' ');
+        if separator {
+            encoded.push(' ');
+        } else {
+            push_encoded(&mut encoded, c, &chars[i + 1..]);
+        }
+    }
+
+    let words: Vec<String> = encoded
+        .split(' ')
+        .enumerate()
+        .map(|(i, word)| {
+            if (word.starts_with('+') || word.starts_with('@')) && word.len() > 1 {
+                format!("%{:02X}{}", word.as_bytes()[0], &word[1..])
+            } else if word.split_once(':').is_some_and(|(key, _)| KEYS.contains(&key)) {
+                word.replacen(':', "%3A", 1)
+            } else if i == 0 && NaiveDate::parse_from_str(word, "%Y-%m-%d").is_ok() {
+                format!("%{:02X}{}", word.as_bytes()[0], &word[1..])
+            } else {
+                word.to_string()
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+/// A value with all whitespace encoded, so it stays one word.
+fn encode(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let mut encoded = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        push_encoded(&mut encoded, c, &chars[i + 1..]);
+    }
+    encoded
+}
+
+/// `%` only needs encoding where it would otherwise decode as an escape.
+fn push_encoded(out: &mut String, c: char, rest: &[char]) {
+    let looks_escaped = rest.len() >= 2 && rest[0].is_ascii_hexdigit() && rest[1].is_ascii_hexdigit();
+    match c {
+        ' ' | '\t' | '\n' | '\r' => out.push_str(&format!("%{:02X}", c as u32)),
+        '%' if looks_escaped => out.push_str("%25"),
+        c => out.push(c),
+    }
+}
+
+/// Decodes `%XX` escapes of ASCII characters and leaves anything else alone.
+fn decode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(at) = rest.find('%') {
+        out.push_str(&rest[..at]);
+        let escaped = rest
+            .get(at + 1..at + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(|byte| byte.is_ascii());
+        match escaped {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[at + 3..];
+            }
+            None => {
+                out.push('%');
+                rest = &rest[at + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_plain_todo_txt() {
+        let todos = import(
+            "(A) 2026-10-01 Call mom +family @phone due:2026-10-20\n\
+             \n\
+             x 2026-10-05 2026-10-02 Pay bills @home +finance +extra pri:C\n\
+             Visit http://example.com\n",
+        )
+        .unwrap();
+
+        assert_eq!(todos.len(), 3);
+        assert_eq!(todos[0].text, "Call mom");
+        assert_eq!(todos[0].priority, Some(Priority::High));
+        assert_eq!(todos[0].project.as_deref(), Some("family"));
+        assert_eq!(todos[0].tags, vec!["phone"]);
+        assert_eq!(todos[0].due.unwrap().to_string(), "2026-10-20 00:00:00");
+        assert_eq!(todos[0].created_at.to_rfc3339(), "2026-10-01T00:00:00+00:00");
+
+        assert!(todos[1].done);
+        assert_eq!(todos[1].text, "Pay bills +extra");
+        assert_eq!(todos[1].priority, Some(Priority::Low));
+        assert_eq!(todos[1].completed_at.unwrap().to_rfc3339(), "2026-10-05T00:00:00+00:00");
+        assert_eq!(todos[1].created_at.to_rfc3339(), "2026-10-02T00:00:00+00:00");
+
+        assert_eq!(todos[2].text, "Visit http://example.com");
+    }
+
+    #[test]
+    fn reports_bad_values_with_line_number() {
+        let err = import("fine\nbroken due:someday\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("someday"));
+    }
+
+    #[test]
+    fn leading_date_in_text_is_not_a_creation_date() {
+        let mut todo = blank();
+        todo.text = "2026-01-01 party".into();
+        todo.done = true;
+
+        let back = parse_line(&line(&todo)).unwrap();
+        assert_eq!(back.text, "2026-01-01 party");
+        assert_eq!(back.created_at, todo.created_at);
+    }
+
+    #[test]
+    fn decode_leaves_stray_percent_signs() {
+        assert_eq!(decode("50% off %zz %4"), "50% off %zz %4");
+        assert_eq!(decode("a%20b%0A%25"), "a b\n%");
+        assert_eq!(decode(&encode("%41 \t")), "%41 \t");
+    }
+}
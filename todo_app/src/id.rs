@@ -0,0 +1,156 @@
+/**
+    Stable todo IDs.
+
+    Every todo gets a ULID, written in lowercase Crockford base32. ULIDs sort
+    by creation time and are unique without any coordination, so they survive
+    reordering, deletion and (later) syncing lists between machines.
+
+    Commands take either an ID prefix or a 1-based position. Positions are
+    only a display convenience: they shift whenever the list changes, so
+    scripts should use IDs.
+*/
+use crate::todo::Todo;
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use ulid::Ulid;
+
+/// Shortest ID prefix `list` prints, even when fewer characters would do.
+const MIN_SHORT_LEN: usize = 6;
+
+pub fn new_id() -> String {
+    Ulid::new().to_string().to_lowercase()
+}
+
+/**
+    ID for a todo saved before IDs existed.
+
+    It has to come out the same on every load until the list is next saved,
+    otherwise `list` would show a different ID each time. So instead of
+    random bits it uses the creation time plus an FNV-1a hash of the
+    position and text, which the old formats already store.
+*/
+fn legacy_id(created_at: DateTime<Utc>, position: usize, text: &str) -> String {
+    let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+    for byte in position.to_le_bytes().iter().chain(text.as_bytes()) {
+        hash ^= *byte as u128;
+        hash = hash.wrapping_mul(0x0000000001000000000000000000013b);
+    }
+
+    let millis = created_at.timestamp_millis().max(0) as u64;
+    Ulid::from_parts(millis, hash).to_string().to_lowercase()
+}
+
+/// Gives every todo loaded from an older file an ID.
+pub fn fill_missing(todos: &mut [Todo]) {
+    for (position, todo) in todos.iter_mut().enumerate() {
+        if todo.id.is_empty() {
+            todo.id = legacy_id(todo.created_at, position, &todo.text);
+        }
+    }
+}
+
+/**
+    Finds the todo that `arg` refers to and returns its index.
+
+    All-digit arguments are positions. The third character of a ULID made
+    between 2025 and 2037 is always a letter, so a number of three or more
+    digits can't be mistaken for an ID prefix. Anything else must be the
+    start of exactly one ID.
+*/
+pub fn resolve(todos: &[Todo], arg: &str) -> Result<usize, Box<dyn Error>> {
+    if let Ok(n) = arg.parse::<usize>() {
+        if n >= 1 && n <= todos.len() {
+            return Ok(n - 1);
+        }
+        return Err(format!("Invalid todo number: {}", arg).into());
+    }
+
+    let prefix = arg.to_lowercase();
+    let mut matches = todos
+        .iter()
+        .enumerate()
+        .filter(|(_, todo)| todo.id.starts_with(&prefix));
+
+    match (matches.next(), matches.next()) {
+        (Some((i, _)), None) => Ok(i),
+        (None, _) => Err(format!("No todo with ID {}", arg).into()),
+        (Some(_), Some(_)) => Err(format!("ID prefix {} matches more than one todo", arg).into()),
+    }
+}
+
+/// How many leading characters tell every ID in `todos` apart.
+pub fn short_len(todos: &[Todo]) -> usize {
+    let mut ids: Vec<&str> = todos.iter().map(|todo| todo.id.as_str()).collect();
+    ids.sort_unstable();
+
+    let longest_shared = ids
+        .windows(2)
+        .map(|pair| pair[0].chars().zip(pair[1].chars()).take_while(|(a, b)| a == b).count())
+        .max()
+        .unwrap_or(0);
+
+    (longest_shared + 1).max(MIN_SHORT_LEN)
+}
+
+pub fn short(id: &str, len: usize) -> &str {
+    &id[..len.min(id.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_ids(ids: &[&str]) -> Vec<Todo> {
+        ids.iter()
+            .map(|id| {
+                let mut todo = Todo::new(id);
+                todo.id = id.to_string();
+                todo
+            })
+            .collect()
+    }
+
+    #[test]
+    fn new_ids_are_unique_lowercase_ulids() {
+        let a = new_id();
+        let b = new_id();
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 26);
+        assert_eq!(a, a.to_lowercase());
+        assert!(Ulid::from_string(&a.to_uppercase()).is_ok());
+    }
+
+    #[test]
+    fn legacy_ids_are_stable() {
+        let mut first = vec![Todo::new("a"), Todo::new("b")];
+        for todo in first.iter_mut() {
+            todo.id.clear();
+        }
+        let mut second = first.clone();
+
+        fill_missing(&mut first);
+        fill_missing(&mut second);
+
+        assert_eq!(first, second);
+        assert_ne!(first[0].id, first[1].id);
+    }
+
+    #[test]
+    fn resolves_positions_and_prefixes() {
+        let todos = with_ids(&["01kabc", "01kabd", "01kxyz"]);
+
+        assert_eq!(resolve(&todos, "2").unwrap(), 1);
+        assert_eq!(resolve(&todos, "01KX").unwrap(), 2);
+        assert_eq!(resolve(&todos, "01kabd").unwrap(), 1);
+        assert!(resolve(&todos, "01kab").is_err());
+        assert!(resolve(&todos, "4").is_err());
+        assert!(resolve(&todos, "zzz").is_err());
+    }
+
+    #[test]
+    fn short_len_separates_every_id() {
+        let todos = with_ids(&["01kabcdefgh", "01kabcdefxy", "01kzz"]);
+        assert_eq!(short_len(&todos), 10);
+        assert_eq!(short_len(&todos[2..]), MIN_SHORT_LEN);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::todo::Todo;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// How many changes `undo` can walk back through.
+const MAX_ENTRIES: usize = 50;
+
+/**
+    One recorded mutation of the list.
+
+    Instead of two full snapshots, only the slice that differs is kept:
+    everything before `start` and after the changed region was identical.
+    Every command edits one contiguous area (an append, an edit, a move
+    between two positions), so entries stay small even for long lists.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub description: String,
+    pub at: DateTime<Utc>,
+    start: usize,
+    removed: Vec<Todo>,
+    inserted: Vec<Todo>,
+}
+
+impl Change {
+    fn between(description: &str, before: &[Todo], after: &[Todo]) -> Change {
+        let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+        let suffix = before[prefix..]
+            .iter()
+            .rev()
+            .zip(after[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        Change {
+            description: description.to_string(),
+            at: Utc::now(),
+            start: prefix,
+            removed: before[prefix..before.len() - suffix].to_vec(),
+            inserted: after[prefix..after.len() - suffix].to_vec(),
+        }
+    }
+
+    /// Swaps `from` back out for `to`, refusing if the list no longer holds
+    /// `from` at the recorded position.
+    fn splice(&self, todos: &mut Vec<Todo>, from: &[Todo], to: &[Todo]) -> Result<(), Box<dyn Error>> {
+        let end = self.start + from.len();
+        if end > todos.len() || todos[self.start..end] != *from {
+            return Err(format!(
+                "Cannot revert \"{}\": the list has changed since then",
+                self.description
+            )
+            .into());
+        }
+
+        todos.splice(self.start..end, to.iter().cloned());
+        Ok(())
+    }
+}
+
+/// Undo and redo stacks, kept next to the list as `<file>.journal.json`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Journal {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl Journal {
+    pub fn load(path: &Path) -> Result<Journal, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Journal::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("{} is corrupt: {}", path.display(), e).into())
+    }
+
+    /// Written to a temporary file and renamed into place, like `todo.json`.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Records a new change. Anything that was undone can no longer be redone.
+    pub fn record(&mut self, description: &str, before: &[Todo], after: &[Todo]) {
+        if before == after {
+            return;
+        }
+
+        self.undo.push(Change::between(description, before, after));
+        if self.undo.len() > MAX_ENTRIES {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Reverts up to `count` changes, newest first, and returns what was undone.
+    pub fn undo(&mut self, todos: &mut Vec<Todo>, count: usize) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut undone = Vec::new();
+        for _ in 0..count {
+            let Some(change) = self.undo.pop() else { break };
+            if let Err(e) = change.splice(todos, &change.inserted, &change.removed) {
+                self.undo.push(change);
+                return Err(e);
+            }
+            undone.push(change.description.clone());
+            self.redo.push(change);
+        }
+        Ok(undone)
+    }
+
+    /// Re-applies up to `count` undone changes, oldest first.
+    pub fn redo(&mut self, todos: &mut Vec<Todo>, count: usize) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut redone = Vec::new();
+        for _ in 0..count {
+            let Some(change) = self.redo.pop() else { break };
+            if let Err(e) = change.splice(todos, &change.removed, &change.inserted) {
+                self.redo.push(change);
+                return Err(e);
+            }
+            redone.push(change.description.clone());
+            self.undo.push(change);
+        }
+        Ok(redone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(texts: &[&str]) -> Vec<Todo> {
+        texts.iter().map(|t| Todo::new(t)).collect()
+    }
+
+    #[test]
+    fn records_only_the_changed_slice() {
+        let before = list(&["a", "b", "c", "d"]);
+        let mut after = before.clone();
+        after[2].text = "C".to_string();
+
+        let change = Change::between("edit", &before, &after);
+        assert_eq!(change.start, 2);
+        assert_eq!(change.removed, vec![before[2].clone()]);
+        assert_eq!(change.inserted, vec![after[2].clone()]);
+    }
+
+    #[test]
+    fn undo_and_redo_several_changes() {
+        let mut journal = Journal::default();
+        let v0 = list(&["a", "b"]);
+
+        let mut v1 = v0.clone();
+        v1.push(Todo::new("c"));
+        journal.record("add c", &v0, &v1);
+
+        let mut v2 = v1.clone();
+        v2.remove(0);
+        journal.record("rm a", &v1, &v2);
+
+        let mut todos = v2.clone();
+        assert_eq!(journal.undo(&mut todos, 2).unwrap(), vec!["rm a", "add c"]);
+        assert_eq!(todos, v0);
+
+        assert_eq!(journal.redo(&mut todos, 1).unwrap(), vec!["add c"]);
+        assert_eq!(todos, v1);
+        assert_eq!(journal.redo(&mut todos, 5).unwrap(), vec!["rm a"]);
+        assert_eq!(todos, v2);
+    }
+
+    #[test]
+    fn new_change_clears_redo() {
+        let mut journal = Journal::default();
+        let v0 = list(&["a"]);
+        let v1 = list(&["a", "b"]);
+        journal.record("add b", &v0, &v1);
+
+        let mut todos = v1.clone();
+        journal.undo(&mut todos, 1).unwrap();
+        journal.record("add c", &todos.clone(), &list(&["a", "c"]));
+
+        assert!(journal.redo(&mut todos, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_to_undo_over_other_edits() {
+        let mut journal = Journal::default();
+        let v0 = list(&["a"]);
+        let mut v1 = v0.clone();
+        v1[0].text = "b".to_string();
+        journal.record("edit", &v0, &v1);
+
+        let mut todos = v1.clone();
+        todos[0].text = "changed elsewhere".to_string();
+
+        assert!(journal.undo(&mut todos, 1).is_err());
+        assert_eq!(todos[0].text, "changed elsewhere");
+    }
+}
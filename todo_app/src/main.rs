@@ -1,96 +1,855 @@
-use serde::{Serialize, Deserialize};
-use std::fs::{File};
-use std::io::{BufReader};
+mod formats;
+mod id;
+mod journal;
+mod merge;
+mod nlp;
+mod query;
+mod recur;
+mod remind;
+mod storage;
+mod timelog;
+mod todo;
+mod tree;
+
+use chrono::{Days, Local, TimeDelta, Utc};
+use std::env;
+use std::error::Error;
+use std::fs;
 use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use formats::Format;
+use id::resolve;
+use query::Query;
+use recur::Recurrence;
+use storage::{load_todos, migrate_to, update, Backend, List, SyncReport, DEFAULT_LIST, PROJECT_LIST};
+use todo::{format_due, parse_due, Priority, Todo};
+
+fn list_todos(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let query = Query::parse(args)?;
+    let todos = load_todos()?;
+    let id_len = id::short_len(&todos);
+
+    let groups = query.run(&todos);
+    let grouped = groups.len() > 1 || groups.iter().any(|g| g.label.is_some());
+
+    for (n, group) in groups.iter().enumerate() {
+        if grouped {
+            if n > 0 {
+                println!();
+            }
+            println!("{}", group.label.as_deref().unwrap_or("(none)"));
+        }
+
+        for (i, branch) in tree::layout(&todos, &group.items) {
+            let todo = &todos[i];
+            let status = if todo.done { "[x]" } else { "[ ]" };
+            let summary = summary(&todos, i, id_len);
+            println!("{} {} {}{} {}{}", i + 1, id::short(&todo.id, id_len), branch, status, todo.text, summary);
+        }
+    }
+
+    Ok(())
+}
+
+/// Short one-line suffix with the optional fields that are set, how far
+/// along the subtasks are and what the todo is still waiting on.
+fn summary(todos: &[Todo], i: usize, id_len: usize) -> String {
+    let todo = &todos[i];
+    let mut parts = Vec::new();
+
+    if let Some((done, total)) = tree::progress(todos, i) {
+        parts.push(format!("{}/{} done", done, total));
+    }
+    let blockers = tree::open_blockers(todos, i);
+    if !blockers.is_empty() {
+        let ids: Vec<&str> = blockers.iter().map(|&b| id::short(&todos[b].id, id_len)).collect();
+        parts.push(format!("blocked by {}", ids.join(",")));
+    }
+
+    if let Some(priority) = todo.priority {
+        parts.push(format!("!{}", priority));
+    }
+    if let Some(due) = &todo.due {
+        parts.push(format!("due {}", format_due(due)));
+    }
+    if let Some(remind_at) = &todo.remind_at {
+        parts.push(format!("remind {}", format_due(remind_at)));
+    }
+    if !todo.sessions.is_empty() {
+        let tracked = timelog::format_duration(timelog::total(todo, Utc::now()));
+        let running = if todo.sessions.iter().any(|s| s.end.is_none()) { ", running" } else { "" };
+        parts.push(format!("{} tracked{}", tracked, running));
+    }
+    if let Some(recur) = &todo.recur {
+        parts.push(format!("every {}", recur));
+    }
+    if let Some(project) = &todo.project {
+        parts.push(format!("+{}", project));
+    }
+    for tag in &todo.tags {
+        parts.push(format!("#{}", tag));
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Todo {
-    text: String,
-    done: bool
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("  ({})", parts.join(" "))
+    }
+}
+
+fn show_todo(arg: &str) -> Result<(), Box<dyn Error>> {
+    let todos = load_todos()?;
+    let i = resolve(&todos, arg)?;
+    let todo = &todos[i];
+    println!("ID:        {}", todo.id);
+    println!("Text:      {}", todo.text);
+    println!("Done:      {}", if todo.done { "yes" } else { "no" });
+    if let Some(priority) = todo.priority {
+        println!("Priority:  {}", priority);
+    }
+    if let Some(due) = &todo.due {
+        println!("Due:       {}", format_due(due));
+    }
+    if let Some(remind_at) = &todo.remind_at {
+        println!("Remind:    {}", format_due(remind_at));
+    }
+    if let Some(recur) = &todo.recur {
+        println!("Repeats:   {}", recur);
+    }
+    if let Some(project) = &todo.project {
+        println!("Project:   {}", project);
+    }
+    if !todo.tags.is_empty() {
+        println!("Tags:      {}", todo.tags.join(", "));
+    }
+    if let Some(parent) = tree::parent(&todos, i) {
+        println!("Parent:    {} ({})", todos[parent].text, todos[parent].id);
+    }
+    if let Some((done, total)) = tree::progress(&todos, i) {
+        println!("Subtasks:  {}/{} done", done, total);
+    }
+    for blocker in tree::open_blockers(&todos, i) {
+        println!("Blocked:   {} ({})", todos[blocker].text, todos[blocker].id);
+    }
+    if !todo.sessions.is_empty() {
+        let tracked = timelog::format_duration(timelog::total(todo, Utc::now()));
+        match todo.sessions.iter().find(|s| s.end.is_none()) {
+            Some(running) => {
+                let since = running.start.with_timezone(&Local).format("%H:%M");
+                println!("Tracked:   {} (running since {})", tracked, since);
+            }
+            None => println!("Tracked:   {} in {} sessions", tracked, todo.sessions.len()),
+        }
+    }
+    println!("Created:   {}", todo.created_at.format("%Y-%m-%d %H:%M UTC"));
+    if let Some(completed_at) = &todo.completed_at {
+        println!("Completed: {}", completed_at.format("%Y-%m-%d %H:%M UTC"));
+    }
+    if let Some(notes) = &todo.notes {
+        println!("Notes:\n{}", notes);
+    }
+
+    Ok(())
 }
 
-const FILE: &str = "todo.json";
+/// Todos a new one should be linked to, as given to `add`. They can only be
+/// resolved once the list is loaded.
+#[derive(Default)]
+struct Links {
+    parent: Option<String>,
+    blocked_by: Vec<String>,
+}
+
+/// Builds a todo from `add` arguments: words make up the text and
+/// `--priority`, `--due`, `--remind`, `--tag`, `--project`, `--note`, `--every`,
+/// `--parent` and `--blocked-by` set the rest. Markers and a trailing due
+/// date in the text are picked out by `nlp::extract` unless `--literal` is
+/// given; explicit flags win over them.
+fn parse_add_args(args: &[String]) -> Result<(Todo, Links), Box<dyn Error>> {
+    let mut words = Vec::new();
+    let mut todo = Todo::new("");
+    let mut links = Links::default();
+    let mut literal = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        match flag {
+            "--priority" | "--due" | "--remind" | "--tag" | "--project" | "--note" | "--every" | "--parent"
+            | "--blocked-by" => {
+                let value = args
+                    .get(i + 1)
+                    .ok_or_else(|| format!("Missing value for {}", flag))?;
+                match flag {
+                    "--priority" => todo.priority = Some(value.parse::<Priority>()?),
+                    "--due" => todo.due = Some(parse_due(value)?),
+                    "--remind" => todo.remind_at = Some(parse_due(value)?),
+                    "--tag" => todo.add_tag(value),
+                    "--project" => todo.project = Some(value.clone()),
+                    "--every" => todo.recur = Some(value.parse::<Recurrence>()?),
+                    "--parent" => links.parent = Some(value.clone()),
+                    "--blocked-by" => links.blocked_by.push(value.clone()),
+                    _ => todo.notes = Some(value.clone()),
+                }
+                i += 2;
+            }
+            "--literal" => {
+                literal = true;
+                i += 1;
+            }
+            _ => {
+                words.push(args[i].clone());
+                i += 1;
+            }
+        }
+    }
+
+    if literal {
+        todo.text = words.join(" ");
+    } else {
+        let extracted = nlp::extract(&words, Local::now().naive_local());
+        todo.text = extracted.text;
+        todo.due = todo.due.or(extracted.due);
+        todo.priority = todo.priority.or(extracted.priority);
+        todo.project = todo.project.or(extracted.project);
+        for tag in &extracted.tags {
+            todo.add_tag(tag);
+        }
+    }
 
-fn load_todos() -> Vec<Todo> {
-    if !Path::new(FILE).exists() {
-        return Vec::new();
+    if todo.text.trim().is_empty() {
+        return Err("Todo text is required".into());
     }
 
-    let file: File = File::open(FILE).expect("Failed to open todo file");
-    let reader: BufReader<File> = BufReader::new(file);
+    Ok((todo, links))
+}
+
+fn add_todo(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (new_todo, links) = parse_add_args(args)?;
+    let text = new_todo.text.clone();
+    let id = new_todo.id.clone();
+
+    update(&format!("add \"{}\"", text), |todos| {
+        let parent = links.parent.as_deref().map(|arg| resolve(todos, arg)).transpose()?;
+        let blockers = links
+            .blocked_by
+            .iter()
+            .map(|arg| resolve(todos, arg))
+            .collect::<Result<Vec<usize>, _>>()?;
+
+        todos.push(new_todo);
+        let i = todos.len() - 1;
+        tree::set_parent(todos, i, parent)?;
+        for blocker in blockers {
+            tree::block(todos, i, blocker)?;
+        }
+        Ok(())
+    })?;
+    println!("Todo added: {} ({})", text, id);
+    Ok(())
+}
+
+/// Marks a todo done or not done. A todo with open subtasks is only
+/// completed with `--force`, which completes the subtasks too.
+fn set_done(args: &[String], done: bool) -> Result<(), Box<dyn Error>> {
+    let (arg, force) = match args {
+        [arg] => (arg, false),
+        [arg, flag] | [flag, arg] if done && flag == "--force" => (arg, true),
+        _ => return Err("Please provide the todo ID or number.".into()),
+    };
 
-    serde_json::from_reader(reader).unwrap_or_else(|_| Vec::new())
+    let verb = if done { "done" } else { "undone" };
+    let next = update(&format!("{} {}", verb, args.join(" ")), |todos| {
+        let i = resolve(todos, arg)?;
+        if !done {
+            todos[i].reopen();
+            return Ok(None);
+        }
+
+        let open: Vec<usize> = tree::descendants(todos, i).into_iter().filter(|&d| !todos[d].done).collect();
+        if !open.is_empty() && !force {
+            return Err(format!(
+                "\"{}\" still has {} open subtasks; finish them first or use done --force",
+                todos[i].text,
+                open.len()
+            )
+            .into());
+        }
+        for d in open {
+            todos[d].complete();
+        }
+
+        Ok(recur::complete(todos, i, Local::now().naive_local()))
+    })?;
+
+    println!("Todo marked as {}!", verb);
+    if let Some(next) = next.as_ref().and_then(|todo| todo.due) {
+        println!("Next occurrence due {}", format_due(&next));
+    }
+    Ok(())
 }
 
-fn save_todos(todos: &Vec<Todo>) {
-    let file: File = File::create(FILE).expect("Failed to create the file");
-    serde_json::to_writer_pretty(file, todos).expect("Failed to write todos");
+fn set_reminder(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [arg, when @ ..] = args else {
+        return Err("Usage: todo remind <id|number> <when|none>".into());
+    };
+    let when = when.join(" ");
+    if when.is_empty() {
+        return Err("Usage: todo remind <id|number> <when|none>".into());
+    }
+    let remind_at = if when == "none" { None } else { Some(parse_due(&when)?) };
+
+    let text = update(&format!("remind {} {}", arg, when), |todos| {
+        let i = resolve(todos, arg)?;
+        todos[i].remind_at = remind_at;
+        Ok(todos[i].text.clone())
+    })?;
+
+    match remind_at {
+        Some(at) => println!("Will remind about \"{}\" at {}", text, format_due(&at)),
+        None => println!("Removed the reminder for \"{}\"", text),
+    }
+    Ok(())
 }
 
-fn list_todos() {
-    let todos = load_todos();
+/**
+    Checks the list every so often and sends reminders for todos that have
+    come due, are about to, or have a reminder set for now. Meant to be
+    left running, e.g. as `todo daemon &` or from a user service.
+*/
+fn run_daemon(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut notifier = remind::notifier("stdout")?;
+    let mut every = Duration::from_secs(60);
+    let mut ahead = TimeDelta::minutes(15);
+    let mut once = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        if flag == "--once" {
+            once = true;
+            i += 1;
+            continue;
+        }
+
+        let value = args.get(i + 1).ok_or_else(|| format!("Missing value for {}", flag))?;
+        match flag {
+            "--notify" => notifier = remind::notifier(value)?,
+            "--every" => match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => every = Duration::from_secs(seconds),
+                _ => return Err(format!("Invalid number of seconds: {}", value).into()),
+            },
+            "--ahead" => {
+                let minutes = value.parse::<i64>().ok().filter(|m| (0..=remind::MAX_AHEAD_MINUTES).contains(m));
+                ahead = minutes.and_then(TimeDelta::try_minutes).ok_or_else(|| {
+                    format!("Invalid number of minutes: {} (expected 0 to {})", value, remind::MAX_AHEAD_MINUTES)
+                })?;
+            }
+            _ => {
+                return Err("Usage: todo daemon [--notify stdout|command:PROGRAM|webhook:URL] [--every SECONDS] \
+                            [--ahead MINUTES] [--once]"
+                    .into());
+            }
+        }
+        i += 2;
+    }
 
-    for (i, todo) in todos.iter().enumerate() {
-        let status = if todo.done { "[x]" } else { "[ ]" };
-        println!("{} {} {}", i + 1, status, todo.text);
+    let sent_path = storage::current_list()?.file(".reminders.json");
+    loop {
+        // A bad moment, like the list being mid-migration, shouldn't stop the daemon.
+        let now = Local::now().naive_local();
+        let checked = load_todos()
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|todos| remind::send_pending(&todos, &sent_path, notifier.as_ref(), now, ahead));
+        match checked {
+            Err(e) if once => return Err(e),
+            Err(e) => eprintln!("Warning: {}", e),
+            Ok(_) => {}
+        }
+
+        if once {
+            return Ok(());
+        }
+        thread::sleep(every);
     }
 }
 
-fn add_todo(text: &str) {
-   let mut todo = load_todos();
-   todo.push(Todo { text: text.to_string(), done: false });
-   save_todos(&todo);
-   println!("Todo added: {}", text)
+fn edit_todo(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [arg, words @ ..] = args else {
+        return Err("Usage: todo edit <id|number> <new text>".into());
+    };
+    if words.is_empty() {
+        return Err("Usage: todo edit <id|number> <new text>".into());
+    }
+
+    let text = words.join(" ");
+    let old = update(&format!("edit {}", arg), |todos| {
+        let i = resolve(todos, arg)?;
+        Ok(std::mem::replace(&mut todos[i].text, text.clone()))
+    })?;
+    println!("Todo renamed: {} -> {}", old, text);
+    Ok(())
 }
 
-fn mark_done(index: usize) {
-    let mut todos = load_todos();
-    if index == 0 || index > todos.len() {
-        println!("Invalid todo number");
-        return;
+fn remove_todos(args: &[String]) -> Result<(), Box<dyn Error>> {
+    if args.is_empty() {
+        return Err("Usage: todo rm <id|number>...".into());
     }
 
-    todos[index - 1].done = true;
-    save_todos(&todos);
-    println!("Todo marked as done!");
+    let removed = update(&format!("rm {}", args.join(" ")), |todos| {
+        let mut indices = args
+            .iter()
+            .map(|arg| resolve(todos, arg))
+            .collect::<Result<Vec<usize>, _>>()?;
+        indices.sort_unstable();
+        indices.dedup();
+
+        // Remove from the back so earlier indices stay valid.
+        let mut removed: Vec<Todo> = indices.iter().rev().map(|&i| todos.remove(i)).collect();
+        removed.reverse();
+        tree::forget(todos, &removed);
+        Ok(removed)
+    })?;
+
+    for todo in removed {
+        println!("Todo removed: {}", todo.text);
+    }
+    Ok(())
 }
 
-fn print_help() {
-    println!("Todo App Commands");
-    println!("add <todo text>");
-    println!("list");
-    println!("done <number>");
-    println!("help");
+fn move_todo(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [from, to] = args else {
+        return Err("Usage: todo mv <id|number> <new position>".into());
+    };
+
+    update(&format!("mv {} {}", from, to), |todos| {
+        let from = resolve(todos, from)?;
+        let to = resolve(todos, to)?;
+        let todo = todos.remove(from);
+        todos.insert(to, todo);
+        Ok(())
+    })?;
+    println!("Todo moved to position {}", to);
+    Ok(())
 }
 
+fn clear_todos(args: &[String]) -> Result<(), Box<dyn Error>> {
+    if args != ["--done"] {
+        return Err("Usage: todo clear --done".into());
+    }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let count = update("clear --done", |todos| {
+        let (removed, kept): (Vec<Todo>, Vec<Todo>) = todos.drain(..).partition(|todo| todo.done);
+        *todos = kept;
+        tree::forget(todos, &removed);
+        Ok(removed.len())
+    })?;
+    println!("Removed {} completed todos", count);
+    Ok(())
+}
 
-    if args.len() < 2 {
-        print_help();
+fn set_parent(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [arg, parent] = args else {
+        return Err("Usage: todo parent <id|number> <parent id|number|none>".into());
+    };
+
+    let message = update(&format!("parent {} {}", arg, parent), |todos| {
+        let i = resolve(todos, arg)?;
+        let p = if parent == "none" { None } else { Some(resolve(todos, parent)?) };
+        tree::set_parent(todos, i, p)?;
+        Ok(match p {
+            Some(p) => format!("\"{}\" is now a subtask of \"{}\"", todos[i].text, todos[p].text),
+            None => format!("\"{}\" is now a top-level todo", todos[i].text),
+        })
+    })?;
+    println!("{}", message);
+    Ok(())
+}
+
+/// `block` makes a todo wait on others; `unblock` undoes that.
+fn set_blocked(args: &[String], blocked: bool) -> Result<(), Box<dyn Error>> {
+    let command = if blocked { "block" } else { "unblock" };
+    let [arg, blockers @ ..] = args else {
+        return Err(format!("Usage: todo {} <id|number> <blocking id|number>...", command).into());
+    };
+    if blockers.is_empty() {
+        return Err(format!("Usage: todo {} <id|number> <blocking id|number>...", command).into());
+    }
+
+    let state = if blocked { "now waits on" } else { "no longer waits on" };
+    let messages = update(&format!("{} {}", command, args.join(" ")), |todos| {
+        let i = resolve(todos, arg)?;
+        let mut messages = Vec::new();
+        for blocker in blockers {
+            let b = resolve(todos, blocker)?;
+            if blocked {
+                tree::block(todos, i, b)?;
+            } else if !tree::unblock(todos, i, b) {
+                return Err(format!("\"{}\" isn't blocked by \"{}\"", todos[i].text, todos[b].text).into());
+            }
+            messages.push(format!("\"{}\" {} \"{}\"", todos[i].text, state, todos[b].text));
+        }
+        Ok(messages)
+    })?;
+
+    for message in messages {
+        println!("{}", message);
+    }
+    Ok(())
+}
+
+fn undo_redo(args: &[String], redo: bool) -> Result<(), Box<dyn Error>> {
+    let count = match args {
+        [] => 1,
+        [n] => n.parse::<usize>().map_err(|_| format!("Invalid number: {}", n))?,
+        _ => return Err("Usage: todo undo|redo [count]".into()),
+    };
+
+    let (descriptions, verb) = if redo {
+        (storage::redo(count)?, "Redid")
+    } else {
+        (storage::undo(count)?, "Undid")
+    };
+
+    if descriptions.is_empty() {
+        println!("Nothing to {}", if redo { "redo" } else { "undo" });
+    }
+    for description in descriptions {
+        println!("{}: {}", verb, description);
+    }
+    Ok(())
+}
+
+fn migrate_storage(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let to = match args {
+        [flag, backend] if flag == "--to" => backend.parse::<Backend>()?,
+        _ => return Err("Usage: todo migrate --to json|sqlite".into()),
+    };
+
+    let count = migrate_to(to)?;
+    println!("Moved {} todos to the {} backend", count, to);
+    Ok(())
+}
+
+fn export_todos(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let format = match args {
+        [] => Format::TodoTxt,
+        [flag, format] if flag == "--format" => format.parse::<Format>()?,
+        _ => return Err("Usage: todo export [--format todotxt|markdown|ics|csv]".into()),
+    };
+
+    print!("{}", format.export(&load_todos()?));
+    Ok(())
+}
+
+/// Adds the todos in a file to the list, skipping any whose ID is already
+/// there, so importing the same export twice doesn't duplicate anything.
+fn import_todos(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (path, format) = match args {
+        [path] => (path, None),
+        [flag, format, path] | [path, flag, format] if flag == "--format" => (path, Some(format.parse::<Format>()?)),
+        _ => return Err("Usage: todo import FILE [--format todotxt|markdown|ics|csv]".into()),
+    };
+
+    let contents = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let format = format.unwrap_or_else(|| Format::detect(Path::new(path), &contents));
+    let imported = format.import(&contents).map_err(|e| format!("{}: {}", path, e))?;
+
+    let total = imported.len();
+    let added = update(&format!("import {}", path), |todos| {
+        let mut added = 0;
+        for todo in imported {
+            if !todos.iter().any(|existing| existing.id == todo.id) {
+                todos.push(todo);
+                added += 1;
+            }
+        }
+        Ok(added)
+    })?;
+    let skipped = total - added;
+
+    println!("Imported {} todos from {} as {}", added, path, format);
+    if skipped > 0 {
+        println!("Skipped {} already in the list", skipped);
+    }
+    Ok(())
+}
+
+fn start_timer(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [arg] = args else {
+        return Err("Usage: todo start <id|number>".into());
+    };
+
+    let text = update(&format!("start {}", arg), |todos| {
+        let i = resolve(todos, arg)?;
+        timelog::start(todos, i, Utc::now())?;
+        Ok(todos[i].text.clone())
+    })?;
+    println!("Started \"{}\" at {}", text, Local::now().format("%H:%M"));
+    Ok(())
+}
+
+fn stop_timer() -> Result<(), Box<dyn Error>> {
+    let stopped = update("stop", |todos| {
+        let now = Utc::now();
+        let (i, length) = timelog::stop(todos, now).ok_or("No timer is running")?;
+        Ok((todos[i].text.clone(), length, timelog::total(&todos[i], now)))
+    })?;
+
+    let (text, length, total) = stopped;
+    println!(
+        "Stopped \"{}\" after {} ({} in total)",
+        text,
+        timelog::format_duration(length),
+        timelog::format_duration(total)
+    );
+    Ok(())
+}
+
+/// Prints time tracked this week, by todo, tag and project.
+fn time_report(args: &[String]) -> Result<(), Box<dyn Error>> {
+    match args {
+        [] => {}
+        [flag] if flag == "--week" => {}
+        _ => return Err("Usage: todo report [--week]".into()),
+    }
+
+    let monday = timelog::week_start(Local::now().date_naive());
+    let (from, to) = (timelog::local_midnight(monday), timelog::local_midnight(monday + Days::new(7)));
+    let report = timelog::report(&load_todos()?, from, to, Utc::now());
+
+    println!("Week of {}: {}", monday.format("%Y-%m-%d"), timelog::format_duration(report.total));
+    for (heading, rows) in [("By todo", &report.tasks), ("By tag", &report.tags), ("By project", &report.projects)] {
+        if rows.is_empty() {
+            continue;
+        }
+        println!();
+        println!("{}", heading);
+        for (name, time) in rows {
+            println!("{:>9}  {}", timelog::format_duration(*time), name);
+        }
+    }
+    Ok(())
+}
+
+/// `todo sync init [REMOTE]` starts keeping the list in git, and `todo sync`
+/// exchanges changes with the remote.
+fn sync_todos(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let report = match args {
+        [] => storage::sync()?,
+        [init] | [init, _] if init == "init" => {
+            let (repo, report) = storage::start_sync(args.get(1).map(String::as_str))?;
+            println!("Committing every change to this list in {}", repo.display());
+            if args.len() == 1 {
+                println!("Add a remote with: git -C {} remote add origin URL", repo.display());
+                return Ok(());
+            }
+            report
+        }
+        _ => return Err("Usage: todo sync [init [REMOTE]]".into()),
+    };
+
+    print_sync_report(&report);
+    Ok(())
+}
+
+fn print_sync_report(report: &SyncReport) {
+    match report.received {
+        0 => println!("Synced; nothing new from other machines"),
+        1 => println!("Synced; 1 todo changed on other machines"),
+        n => println!("Synced; {} todos changed on other machines", n),
+    }
+    for conflict in &report.conflicts {
+        println!("Both machines changed the {} of \"{}\"; kept this one's", conflict.field, conflict.text);
+    }
+}
+
+/// Prints the named lists, marking the one in use, and the project list if
+/// that's what this directory uses.
+fn show_lists() -> Result<(), Box<dyn Error>> {
+    let current = storage::current_list()?;
+    let mut names = storage::named_lists()?;
+    if let Some(name) = current.name.as_ref().filter(|name| !names.contains(name)) {
+        names.push(name.clone());
+        names.sort();
+    }
+
+    for name in &names {
+        let marker = if current.name.as_ref() == Some(name) { "*" } else { " " };
+        println!("{} {}", marker, name);
+    }
+    if current.name.is_none() {
+        let dir = current.base.parent().unwrap_or(Path::new("/"));
+        println!("* project list in {}", dir.display());
+    }
+    Ok(())
+}
+
+/// Starts a project list in the current directory.
+fn init_project() -> Result<(), Box<dyn Error>> {
+    let list = List { name: None, base: env::current_dir()?.join(PROJECT_LIST) };
+    if list.exists() {
+        return Err("This directory already has a project list".into());
+    }
+
+    let store = storage::open(&list, Backend::Json);
+    store.save(&[])?;
+    println!("Created {}", store.path().display());
+    println!("todo commands run in this directory or below it now use this list");
+    Ok(())
+}
+
+/// Lists used to be `todo.json` in whatever directory a command ran in.
+/// Points out such a file until the default list that replaces it exists.
+fn warn_about_old_list() {
+    let Ok(list) = storage::current_list() else {
+        return;
+    };
+    if list.name.as_deref() != Some(DEFAULT_LIST) || list.exists() {
         return;
     }
 
+    for old in ["todo.json", "todo.db"] {
+        if Path::new(old).exists() {
+            let extension = old.trim_start_matches("todo");
+            eprintln!("Note: ./{} is a list from an older version and is no longer read.", old);
+            eprintln!(
+                "Move it to {}{} to keep using it, or to ./{}{} to make it this directory's project list.",
+                list.base.display(),
+                extension,
+                PROJECT_LIST,
+                extension
+            );
+        }
+    }
+}
+
+/// Takes `--list NAME` out of the arguments, wherever it is.
+fn take_list_option(args: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+    let Some(at) = args.iter().position(|arg| arg == "--list") else {
+        return Ok(());
+    };
+    if at + 1 >= args.len() {
+        return Err("Missing value for --list".into());
+    }
+
+    let name = args.remove(at + 1);
+    args.remove(at);
+    storage::select_list(&name)?;
+    Ok(())
+}
+
+fn print_help() {
+    println!("Todo App Commands");
+    println!("Every command takes --list NAME to work on a named list other than the default.");
+    println!();
+    println!("add <todo text> [--priority low|medium|high] [--due YYYY-MM-DD [HH:MM]] [--remind WHEN]");
+    println!("    [--tag TAG]... [--project NAME] [--note TEXT]");
+    println!("    [--every daily|weekdays|weekly:mon,thu|monthly:N|after:N] [--literal]");
+    println!("    [--parent ID] [--blocked-by ID]...");
+    println!("    the text may contain +project, #tag and !priority, and end in a due date:");
+    println!("    tomorrow, next friday 9am, in 3 days, eod, 2026-12-01 14:00 ...");
+    println!("list [FILTER]... [--sort FIELD,...] [--limit N] [--group project|tag]");
+    println!("    filters: status:open|done|all tag:T project:P due<DATE priority>=high \"free text\"");
+    println!("    prefix a filter with - to negate it; sort fields: due priority created text status project");
+    println!("show <id|number>");
+    println!("done <id|number> [--force]   (--force also completes open subtasks)");
+    println!("undone <id|number>");
+    println!("edit <id|number> <new text>");
+    println!("rm <id|number>...");
+    println!("mv <id|number> <new position>");
+    println!("clear --done");
+    println!("parent <id|number> <parent id|number|none>");
+    println!("block <id|number> <blocking id|number>...");
+    println!("unblock <id|number> <blocking id|number>...");
+    println!("remind <id|number> <when|none>   (when to remind, e.g. tomorrow 9am)");
+    println!("daemon [--notify stdout|command:PROGRAM|webhook:URL] [--every SECONDS] [--ahead MINUTES] [--once]");
+    println!("    sends reminders, and notices for todos due within --ahead minutes (15) or overdue");
+    println!("start <id|number>   (start tracking time on a todo)");
+    println!("stop                (stop the running timer)");
+    println!("report [--week]     (time tracked this week by todo, tag and project)");
+    println!("undo [count]");
+    println!("redo [count]");
+    println!("migrate --to json|sqlite");
+    println!("export [--format todotxt|markdown|ics|csv]");
+    println!("import FILE [--format todotxt|markdown|ics|csv]");
+    println!("lists");
+    println!("init    (start a project list in this directory)");
+    println!("sync [init [REMOTE]]   (commit every change to git and merge with REMOTE)");
+    println!("help");
+    println!();
+    println!("Inside a directory with a .todo.json, or below one, commands use that project list.");
+    println!("Otherwise they use the named list in $XDG_DATA_HOME/todo_app (~/.local/share/todo_app).");
+    println!("Todos can be referred to by a prefix of their ID or by their position in list.");
+    println!("Positions change as the list changes, so scripts should use IDs.");
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let rest = &args[2..];
+
     match args[1].as_str() {
         "add" => {
-            if args.len() < 3 {
+            if rest.is_empty() {
                 println!("Usage: todo add <todo text>");
             } else {
-                add_todo(&args[2..].join(" "));
-            }
-        }
-        "list" => list_todos(),
-        "done" => {
-            if args.len() < 3 {
-                println!("Please provide the todo number.");
-            } else  if let Ok(num) = args[2].parse::<usize>() {
-                mark_done(num);
-            } else {
-                println!("Invalid number");
+                add_todo(rest)?;
             }
         }
+        "list" => list_todos(rest)?,
+        "show" => match rest {
+            [arg] => show_todo(arg)?,
+            _ => println!("Please provide the todo ID or number."),
+        },
+        "done" => set_done(rest, true)?,
+        "undone" => set_done(rest, false)?,
+        "edit" => edit_todo(rest)?,
+        "rm" => remove_todos(rest)?,
+        "mv" => move_todo(rest)?,
+        "clear" => clear_todos(rest)?,
+        "parent" => set_parent(rest)?,
+        "block" => set_blocked(rest, true)?,
+        "unblock" => set_blocked(rest, false)?,
+        "undo" => undo_redo(rest, false)?,
+        "redo" => undo_redo(rest, true)?,
+        "migrate" => migrate_storage(rest)?,
+        "export" => export_todos(rest)?,
+        "import" => import_todos(rest)?,
+        "lists" => show_lists()?,
+        "init" => init_project()?,
+        "sync" => sync_todos(rest)?,
+        "remind" => set_reminder(rest)?,
+        "daemon" => run_daemon(rest)?,
+        "start" => start_timer(rest)?,
+        "stop" => stop_timer()?,
+        "report" => time_report(rest)?,
         "help" => print_help(),
         _ => print_help(),
     }
+
+    Ok(())
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+
+    if let Err(e) = take_list_option(&mut args) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+
+    if args.len() < 2 {
+        print_help();
+        return;
+    }
+
+    if !matches!(args[1].as_str(), "help" | "init") {
+        warn_about_old_list();
+    }
+    if let Err(e) = run(&args) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
 }
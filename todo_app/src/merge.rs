@@ -0,0 +1,180 @@
+use crate::todo::Todo;
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A field both sides changed to different values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub text: String,
+    pub field: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Merged {
+    pub todos: Vec<Todo>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/**
+    Three-way merge of two versions of a list that both started out as
+    `base`, matching todos by ID rather than by line, so adding, completing
+    or editing different todos on two machines never conflicts.
+
+    A todo changed on one side only takes that side's version, including
+    being removed. One changed on both sides is merged field by field;
+    where both changed the same field, `ours` wins and the field is
+    reported as a conflict. A todo removed on one side but edited on the
+    other is kept, as dropping it would lose the edit.
+
+    Todos keep the order they have in `ours`, and ones only `theirs` has
+    go at the end. Links to todos that didn't survive the merge are dropped.
+*/
+pub fn merge(base: &[Todo], ours: &[Todo], theirs: &[Todo]) -> Merged {
+    let base: HashMap<&str, &Todo> = base.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    let their_ids: HashMap<&str, &Todo> = theirs.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    let our_ids: HashSet<&str> = ours.iter().map(|todo| todo.id.as_str()).collect();
+
+    let mut merged = Merged::default();
+    for todo in ours {
+        let id = todo.id.as_str();
+        let kept = merge_todo(base.get(id).copied(), Some(todo), their_ids.get(id).copied(), &mut merged.conflicts);
+        merged.todos.extend(kept);
+    }
+    for todo in theirs.iter().filter(|todo| !our_ids.contains(todo.id.as_str())) {
+        let kept = merge_todo(base.get(todo.id.as_str()).copied(), None, Some(todo), &mut merged.conflicts);
+        merged.todos.extend(kept);
+    }
+
+    let ids: HashSet<String> = merged.todos.iter().map(|todo| todo.id.clone()).collect();
+    for todo in merged.todos.iter_mut() {
+        if todo.parent.as_ref().is_some_and(|parent| !ids.contains(parent)) {
+            todo.parent = None;
+        }
+        todo.blocked_by.retain(|blocker| ids.contains(blocker));
+    }
+    merged
+}
+
+fn merge_todo(
+    base: Option<&Todo>,
+    ours: Option<&Todo>,
+    theirs: Option<&Todo>,
+    conflicts: &mut Vec<Conflict>,
+) -> Option<Todo> {
+    match (ours, theirs) {
+        _ if ours == theirs || base == theirs => ours.cloned(),
+        _ if base == ours => theirs.cloned(),
+        (Some(ours), Some(theirs)) => Some(merge_fields(base, ours, theirs, conflicts)),
+        // Removed on one side and edited on the other.
+        (kept, None) | (None, kept) => kept.cloned(),
+    }
+}
+
+/// Merges the serialized fields one by one, which keeps working as fields
+/// are added to `Todo`.
+fn merge_fields(base: Option<&Todo>, ours: &Todo, theirs: &Todo, conflicts: &mut Vec<Conflict>) -> Todo {
+    let fields = |todo: &Todo| match serde_json::to_value(todo) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+    let (base_fields, our_fields, their_fields) = (base.map(fields).unwrap_or_default(), fields(ours), fields(theirs));
+
+    let names: BTreeSet<&String> = our_fields.keys().chain(their_fields.keys()).collect();
+    let mut merged = Map::new();
+    for name in names {
+        let (b, o, t) = (base_fields.get(name), our_fields.get(name), their_fields.get(name));
+        let value = if o == t || b == t {
+            o
+        } else if b == o {
+            t
+        } else {
+            conflicts.push(Conflict { text: ours.text.clone(), field: name.clone() });
+            o
+        };
+        if let Some(value) = value {
+            merged.insert(name.clone(), value.clone());
+        }
+    }
+
+    serde_json::from_value(Value::Object(merged)).unwrap_or_else(|_| ours.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(texts: &[&str]) -> Vec<Todo> {
+        texts.iter().map(|text| Todo::new(text)).collect()
+    }
+
+    #[test]
+    fn changes_to_different_todos_both_apply() {
+        let base = list(&["milk", "bread", "eggs"]);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+
+        ours[0].text = "oat milk".into();
+        ours.push(Todo::new("ours"));
+        theirs[1].complete();
+        theirs.remove(2);
+        theirs.push(Todo::new("theirs"));
+
+        let merged = merge(&base, &ours, &theirs);
+        let texts: Vec<&str> = merged.todos.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["oat milk", "bread", "ours", "theirs"]);
+        assert!(merged.todos[1].done);
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn different_fields_of_one_todo_merge() {
+        let base = list(&["milk"]);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+
+        ours[0].text = "oat milk".into();
+        theirs[0].complete();
+        theirs[0].add_tag("shop");
+
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged.todos[0].text, "oat milk");
+        assert!(merged.todos[0].done && merged.todos[0].completed_at.is_some());
+        assert_eq!(merged.todos[0].tags, vec!["shop"]);
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn same_field_keeps_ours_and_reports_it() {
+        let base = list(&["milk"]);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+
+        ours[0].text = "oat milk".into();
+        theirs[0].text = "soy milk".into();
+        theirs[0].notes = Some("the blue one".into());
+
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged.todos[0].text, "oat milk");
+        assert_eq!(merged.todos[0].notes.as_deref(), Some("the blue one"));
+        assert_eq!(merged.conflicts, vec![Conflict { text: "oat milk".into(), field: "text".into() }]);
+    }
+
+    #[test]
+    fn edit_wins_over_removal_and_dangling_links_go() {
+        let base = list(&["parent", "child", "blocker"]);
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+
+        ours[1].parent = Some(base[0].id.clone());
+        ours[1].blocked_by = vec![base[2].id.clone()];
+        ours.remove(2);
+        theirs.remove(0);
+        theirs[1].text = "blocker, edited".into();
+
+        let merged = merge(&base, &ours, &theirs);
+        let texts: Vec<&str> = merged.todos.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["child", "blocker, edited"]);
+        assert_eq!(merged.todos[0].parent, None);
+        assert_eq!(merged.todos[0].blocked_by, vec![base[2].id.clone()]);
+    }
+}
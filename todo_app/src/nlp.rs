@@ -0,0 +1,323 @@
+/**
+    Picks structured fields out of the words given to `todo add`.
+
+        todo add Send invoice +acme #billing !high next friday 10am
+
+    adds "Send invoice" to project acme, tagged billing, with high priority,
+    due next Friday at 10:00.
+
+    Markers (`+project`, `#tag`, `!priority`) can go anywhere. A due date is
+    only taken from the end of the text, optionally introduced by "by",
+    "due", "on" or "at", so a title like "Plan tomorrow's talk" is left
+    alone. Everything that isn't recognised stays in the title.
+*/
+use crate::todo::Priority;
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+/// Longest date phrase looked for at the end, e.g. "in 3 days at 9am".
+const MAX_PHRASE_WORDS: usize = 5;
+
+/// What "eod" means: the end of the working day.
+const END_OF_DAY: (u32, u32) = (17, 0);
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Extracted {
+    pub text: String,
+    pub due: Option<NaiveDateTime>,
+    pub priority: Option<Priority>,
+    pub project: Option<String>,
+    pub tags: Vec<String>,
+}
+
+pub fn extract(words: &[String], now: NaiveDateTime) -> Extracted {
+    let mut extracted = Extracted::default();
+    let mut rest: Vec<&str> = Vec::new();
+
+    for word in words.iter().flat_map(|w| w.split_whitespace()) {
+        if let Some(project) = marker(word, '+') {
+            extracted.project = Some(project.to_string());
+        } else if let Some(tag) = marker(word, '#') {
+            let tag = tag.to_lowercase();
+            if !extracted.tags.contains(&tag) {
+                extracted.tags.push(tag);
+            }
+        } else if let Some(priority) = word.strip_prefix('!').and_then(|p| p.parse::<Priority>().ok()) {
+            extracted.priority = Some(priority);
+        } else {
+            rest.push(word);
+        }
+    }
+
+    // Try the longest trailing phrase first so "next friday" wins over "friday".
+    let longest = MAX_PHRASE_WORDS.min(rest.len().saturating_sub(1));
+    for len in (1..=longest).rev() {
+        let split = rest.len() - len;
+        if let Some(due) = parse_date(&rest[split..], now) {
+            extracted.due = Some(due);
+            rest.truncate(split);
+            if let Some(last) = rest.last()
+                && rest.len() > 1
+                && ["by", "due", "on", "at"].contains(&last.to_lowercase().as_str())
+            {
+                rest.pop();
+            }
+            break;
+        }
+    }
+
+    extracted.text = rest.join(" ");
+    extracted
+}
+
+/// `+word` or `#word`, where the word has to start with a letter so that
+/// "+1" or "issue #42" stay in the text.
+fn marker(word: &str, sigil: char) -> Option<&str> {
+    let name = word.strip_prefix(sigil)?;
+    if name.starts_with(|c: char| c.is_alphabetic()) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/**
+    Reads a date phrase, relative to `now`:
+
+        today, tonight, tomorrow, eod, eow, next week, next month
+        friday, next friday (the first Friday after today)
+        in 3 days, in 2 weeks, in a month
+        2026-12-01
+
+    Any of them can be followed by a time: `14:00`, `9am`, `5pm`, `noon`,
+    with or without "at" in between. Without a time the todo is due at
+    midnight, except for phrases that imply one (tonight, eod, eow).
+*/
+pub fn parse_date(words: &[&str], now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    let words: Vec<&str> = lower.iter().map(String::as_str).collect();
+
+    // A bare time means today at that time.
+    if let [time] = words[..]
+        && let Some(time) = parse_time(time)
+    {
+        return Some(now.date().and_time(time));
+    }
+
+    for split in (1..=words.len()).rev() {
+        let (date_words, time_words) = words.split_at(split);
+        let Some((date, implied)) = parse_day(date_words, now.date()) else {
+            continue;
+        };
+
+        let time = match time_words {
+            [] => implied.unwrap_or(NaiveTime::MIN),
+            ["at", time] | [time] => parse_time(time)?,
+            _ => continue,
+        };
+        return Some(date.and_time(time));
+    }
+
+    None
+}
+
+/// A weekday's full name, or its three-letter abbreviation if `abbreviated`
+/// is allowed. Abbreviations are ordinary words too ("Put on sun", "Fix
+/// the sat"), so on their own they'd turn the end of a title into a date;
+/// only "next mon" is clear enough.
+fn weekday(word: &str, abbreviated: bool) -> Option<Weekday> {
+    let weekday = word.parse::<Weekday>().ok()?;
+    (abbreviated || word.len() > 3).then_some(weekday)
+}
+
+/// The day a phrase names, and the time it implies, if any.
+fn parse_day(words: &[&str], today: NaiveDate) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    let end_of_day = NaiveTime::from_hms_opt(END_OF_DAY.0, END_OF_DAY.1, 0);
+
+    let day = match words {
+        ["today"] => today,
+        ["tonight"] => return Some((today, NaiveTime::from_hms_opt(20, 0, 0))),
+        ["tomorrow"] | ["tmr"] => today + Days::new(1),
+        ["eod"] => return Some((today, end_of_day)),
+        ["eow"] => return Some((next_weekday(today - Days::new(1), Weekday::Fri), end_of_day)),
+        ["next", "week"] => next_weekday(today, Weekday::Mon),
+        ["next", "month"] => first_of_month(today.checked_add_months(Months::new(1))?),
+        [day] | ["on", day] => match weekday(day, false) {
+            Some(weekday) => next_weekday(today, weekday),
+            None => NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?,
+        },
+        ["next", day] => match weekday(day, true) {
+            Some(weekday) => next_weekday(today, weekday),
+            None => NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?,
+        },
+        ["in", count, unit] => {
+            let count = match *count {
+                "a" | "an" | "one" => 1,
+                n => n.parse::<u32>().ok()?,
+            };
+            // Far enough ahead to leave the calendar isn't a date, just words.
+            match unit.trim_end_matches('s') {
+                "day" => today.checked_add_days(Days::new(count as u64))?,
+                "week" => today.checked_add_days(Days::new(7 * count as u64))?,
+                "month" => today.checked_add_months(Months::new(count))?,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    Some((day, None))
+}
+
+/// The first `weekday` strictly after `date`.
+fn next_weekday(date: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let ahead = (7 + weekday.num_days_from_monday() - date.weekday().num_days_from_monday()) % 7;
+    date + Days::new(if ahead == 0 { 7 } else { ahead as u64 })
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("every month has a first day")
+}
+
+/// `14:00`, `9am`, `9:30pm`, `noon` or `midnight`.
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    match s {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return Some(NaiveTime::MIN),
+        _ => {}
+    }
+
+    let (clock, offset) = if let Some(clock) = s.strip_suffix("am") {
+        (clock, Some(0))
+    } else if let Some(clock) = s.strip_suffix("pm") {
+        (clock, Some(12))
+    } else {
+        (s, None)
+    };
+
+    let (hour, minute) = match clock.split_once(':') {
+        Some((h, m)) if m.len() == 2 => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        None if offset.is_some() => (clock.parse::<u32>().ok()?, 0),
+        _ => return None,
+    };
+
+    let hour = match offset {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Sunday afternoon.
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2026-10-18 15:30", "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn at(s: &str) -> Option<NaiveDateTime> {
+        Some(NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    fn add(text: &str) -> Extracted {
+        let words: Vec<String> = text.split(' ').map(String::from).collect();
+        extract(&words, now())
+    }
+
+    fn date(phrase: &str) -> Option<NaiveDateTime> {
+        let words: Vec<&str> = phrase.split(' ').collect();
+        parse_date(&words, now())
+    }
+
+    #[test]
+    fn relative_days() {
+        assert_eq!(date("today"), at("2026-10-18 00:00"));
+        assert_eq!(date("tomorrow"), at("2026-10-19 00:00"));
+        assert_eq!(date("eod"), at("2026-10-18 17:00"));
+        assert_eq!(date("tonight"), at("2026-10-18 20:00"));
+        assert_eq!(date("in 3 days"), at("2026-10-21 00:00"));
+        assert_eq!(date("in a week"), at("2026-10-25 00:00"));
+        assert_eq!(date("in 2 months"), at("2026-12-18 00:00"));
+        assert_eq!(date("next week"), at("2026-10-19 00:00"));
+        assert_eq!(date("next month"), at("2026-11-01 00:00"));
+
+        // Past the end of the calendar.
+        for phrase in ["in 100000000 days", "in 4000000000 weeks", "in 4000000000 months"] {
+            assert_eq!(date(phrase), None, "{}", phrase);
+        }
+    }
+
+    #[test]
+    fn weekdays_are_always_ahead() {
+        assert_eq!(date("friday"), at("2026-10-23 00:00"));
+        assert_eq!(date("next friday"), at("2026-10-23 00:00"));
+        assert_eq!(date("Sunday"), at("2026-10-25 00:00"));
+        assert_eq!(date("next mon"), at("2026-10-19 00:00"));
+        assert_eq!(date("mon"), None);
+        assert_eq!(date("on sat"), None);
+        assert_eq!(date("eow"), at("2026-10-23 17:00"));
+    }
+
+    #[test]
+    fn dates_and_times() {
+        assert_eq!(date("2026-12-01 14:00"), at("2026-12-01 14:00"));
+        assert_eq!(date("2026-12-01"), at("2026-12-01 00:00"));
+        assert_eq!(date("tomorrow 9am"), at("2026-10-19 09:00"));
+        assert_eq!(date("friday at 5:30pm"), at("2026-10-23 17:30"));
+        assert_eq!(date("today noon"), at("2026-10-18 12:00"));
+        assert_eq!(date("12am"), at("2026-10-18 00:00"));
+        assert_eq!(date("18:45"), at("2026-10-18 18:45"));
+        assert_eq!(date("13pm"), None);
+        assert_eq!(date("someday"), None);
+        assert_eq!(date("in 3 fortnights"), None);
+    }
+
+    #[test]
+    fn extracts_markers_anywhere() {
+        let e = add("Send +acme invoice #Billing !high");
+        assert_eq!(e.text, "Send invoice");
+        assert_eq!(e.project.as_deref(), Some("acme"));
+        assert_eq!(e.tags, vec!["billing"]);
+        assert_eq!(e.priority, Some(Priority::High));
+        assert_eq!(e.due, None);
+    }
+
+    #[test]
+    fn extracts_trailing_due_date() {
+        let e = add("Pay rent by next friday 10am");
+        assert_eq!(e.text, "Pay rent");
+        assert_eq!(e.due, at("2026-10-23 10:00"));
+
+        let e = add("Call the bank in 3 days #admin");
+        assert_eq!(e.text, "Call the bank");
+        assert_eq!(e.due, at("2026-10-21 00:00"));
+        assert_eq!(e.tags, vec!["admin"]);
+
+        assert_eq!(add("Ship it eod").due, at("2026-10-18 17:00"));
+        assert_eq!(add("Release 2026-12-01 14:00").text, "Release");
+    }
+
+    #[test]
+    fn leaves_ordinary_text_alone() {
+        let e = add("Plan tomorrow's talk about issue #42 and C++ +1");
+        assert_eq!(e.text, "Plan tomorrow's talk about issue #42 and C++ +1");
+        assert_eq!(e, Extracted { text: e.text.clone(), ..Extracted::default() });
+
+        // A title that is nothing but a date stays a title.
+        assert_eq!(add("tomorrow").text, "tomorrow");
+        assert_eq!(add("Meet on monday").text, "Meet");
+        assert_eq!(add("!urgent fix").text, "!urgent fix");
+
+        // Weekday abbreviations are words first.
+        for title in ["Put on sun", "Fix the sat", "Call wed", "Start on mon"] {
+            let e = add(title);
+            assert_eq!((e.text.as_str(), e.due), (title, None));
+        }
+        assert_eq!(add("Call Ana next wed").text, "Call Ana");
+
+        let e = add("foo in 100000000 days");
+        assert_eq!((e.text.as_str(), e.due), ("foo in 100000000 days", None));
+    }
+}
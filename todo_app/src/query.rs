@@ -0,0 +1,412 @@
+/**
+    The small query language behind `todo list`.
+
+        todo list status:open tag:work due<2026-11-01 priority>=high "free text"
+        todo list --sort due,-priority --limit 10 --group project
+
+    Every term has to match for a todo to be listed. A term is either
+    `key OP value`, where OP is one of `:` `=` `!=` `<` `<=` `>` `>=`, or
+    plain words, which are searched for in the text and notes. A leading `-`
+    negates a term, e.g. `-tag:someday`.
+
+    Keys: status (open, done, all), tag, project, due (a date or `none`),
+    priority (low, medium, high or `none`).
+*/
+use crate::todo::{parse_due, Priority, Todo};
+use chrono::NaiveDateTime;
+use std::cmp::Ordering;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Status(Option<bool>),
+    Tag(String),
+    Project(Option<String>),
+    Due(Op, Option<NaiveDateTime>),
+    Priority(Op, Option<Priority>),
+    Text(String),
+    Not(Box<Term>),
+}
+
+impl Term {
+    fn matches(&self, todo: &Todo) -> bool {
+        match self {
+            Term::Status(done) => done.is_none_or(|done| todo.done == done),
+            Term::Tag(tag) => todo.tags.iter().any(|t| t == tag),
+            Term::Project(project) => todo.project.as_deref().map(str::to_lowercase) == *project,
+            Term::Due(op, None) => {
+                let missing = todo.due.is_none();
+                if *op == Op::Ne { !missing } else { missing }
+            }
+            Term::Due(op, Some(when)) => match &todo.due {
+                // `due:2026-11-01` means "due that day", whatever the time.
+                Some(due) if matches!(op, Op::Eq | Op::Ne) => op.holds(due.date().cmp(&when.date())),
+                Some(due) => op.holds(due.cmp(when)),
+                None => false,
+            },
+            Term::Priority(op, wanted) => match (todo.priority, wanted) {
+                (Some(p), Some(w)) => op.holds(p.cmp(w)),
+                (p, None) if matches!(op, Op::Eq | Op::Ne) => op.holds(p.is_none().cmp(&true)),
+                _ => false,
+            },
+            Term::Text(words) => {
+                let text = todo.text.to_lowercase();
+                let notes = todo.notes.as_deref().unwrap_or_default().to_lowercase();
+                text.contains(words) || notes.contains(words)
+            }
+            Term::Not(term) => !term.matches(todo),
+        }
+    }
+
+    fn parse(arg: &str) -> Result<Term, String> {
+        if let Some(rest) = arg.strip_prefix('-')
+            && !rest.is_empty()
+        {
+            return Ok(Term::Not(Box::new(Term::parse(rest)?)));
+        }
+
+        let Some((key, op, value)) = split_term(arg) else {
+            return Ok(Term::Text(arg.to_lowercase()));
+        };
+        let value = value.to_lowercase();
+        let eq_only = |term: Term| {
+            if matches!(op, Op::Eq | Op::Ne) {
+                Ok(if op == Op::Ne { Term::Not(Box::new(term)) } else { term })
+            } else {
+                Err(format!("{} only supports : and !=", key))
+            }
+        };
+
+        match key {
+            "status" => {
+                let done = match value.as_str() {
+                    "open" | "todo" | "pending" => Some(false),
+                    "done" | "completed" => Some(true),
+                    "all" | "any" => None,
+                    _ => return Err(format!("Unknown status: {} (expected open, done or all)", value)),
+                };
+                eq_only(Term::Status(done))
+            }
+            "tag" => eq_only(Term::Tag(value.trim_start_matches('#').to_string())),
+            "project" => eq_only(Term::Project(if value == "none" { None } else { Some(value) })),
+            "due" => {
+                let when = if value == "none" { None } else { Some(parse_due(&value)?) };
+                Ok(Term::Due(op, when))
+            }
+            "priority" | "pri" => {
+                let priority = if value == "none" { None } else { Some(value.parse::<Priority>()?) };
+                Ok(Term::Priority(op, priority))
+            }
+            _ => Ok(Term::Text(arg.to_lowercase())),
+        }
+    }
+}
+
+/// Splits `key>=value` into its parts. Returns `None` for plain words.
+fn split_term(arg: &str) -> Option<(&str, Op, &str)> {
+    let start = arg.find([':', '=', '!', '<', '>'])?;
+    let (key, rest) = arg.split_at(start);
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let (op, len) = match rest.as_bytes() {
+        [b'!', b'=', ..] => (Op::Ne, 2),
+        [b'<', b'=', ..] => (Op::Le, 2),
+        [b'>', b'=', ..] => (Op::Ge, 2),
+        [b'<', ..] => (Op::Lt, 1),
+        [b'>', ..] => (Op::Gt, 1),
+        [b':', ..] | [b'=', ..] => (Op::Eq, 1),
+        _ => return None,
+    };
+
+    Some((key, op, &rest[len..]))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortField {
+    Due,
+    Priority,
+    Created,
+    Text,
+    Status,
+    Project,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SortKey {
+    field: SortField,
+    reverse: bool,
+}
+
+impl SortKey {
+    fn parse(s: &str) -> Result<SortKey, String> {
+        let (reverse, name) = match s.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, s),
+        };
+
+        let field = match name {
+            "due" => SortField::Due,
+            "priority" | "pri" => SortField::Priority,
+            "created" => SortField::Created,
+            "text" => SortField::Text,
+            "status" => SortField::Status,
+            "project" => SortField::Project,
+            _ => return Err(format!("Cannot sort by {}", name)),
+        };
+
+        Ok(SortKey { field, reverse })
+    }
+
+    /// Natural order for each field: soonest due, highest priority, oldest,
+    /// A to Z, open before done. Todos without the field go last, even when
+    /// the order is reversed.
+    fn compare(&self, a: &Todo, b: &Todo) -> Ordering {
+        let flip = |ordering: Ordering| if self.reverse { ordering.reverse() } else { ordering };
+
+        match self.field {
+            SortField::Due => missing_last(a.due, b.due, |x, y| flip(x.cmp(&y))),
+            SortField::Priority => missing_last(a.priority, b.priority, |x, y| flip(y.cmp(&x))),
+            SortField::Project => missing_last(a.project.as_ref(), b.project.as_ref(), |x, y| flip(x.cmp(y))),
+            SortField::Created => flip(a.created_at.cmp(&b.created_at)),
+            SortField::Text => flip(a.text.to_lowercase().cmp(&b.text.to_lowercase())),
+            SortField::Status => flip(a.done.cmp(&b.done)),
+        }
+    }
+}
+
+fn missing_last<T>(a: Option<T>, b: Option<T>, cmp: impl FnOnce(T, T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Project,
+    Tag,
+}
+
+/// Todos that belong under one heading, as indices into the loaded list.
+pub struct Group {
+    pub label: Option<String>,
+    pub items: Vec<usize>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    terms: Vec<Term>,
+    sort: Vec<SortKey>,
+    limit: Option<usize>,
+    group: Option<GroupBy>,
+}
+
+impl Query {
+    pub fn parse(args: &[String]) -> Result<Query, Box<dyn Error>> {
+        let mut query = Query::default();
+
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            let value = || args.get(i + 1).ok_or_else(|| format!("Missing value for {}", arg));
+
+            match arg {
+                "--sort" => {
+                    for key in value()?.split(',') {
+                        query.sort.push(SortKey::parse(key.trim())?);
+                    }
+                    i += 2;
+                }
+                "--limit" => {
+                    let n = value()?;
+                    query.limit = Some(n.parse().map_err(|_| format!("Invalid limit: {}", n))?);
+                    i += 2;
+                }
+                "--group" => {
+                    query.group = Some(match value()?.as_str() {
+                        "project" => GroupBy::Project,
+                        "tag" => GroupBy::Tag,
+                        other => return Err(format!("Cannot group by {} (expected project or tag)", other).into()),
+                    });
+                    i += 2;
+                }
+                _ => {
+                    query.terms.push(Term::parse(arg)?);
+                    i += 1;
+                }
+            }
+        }
+
+        Ok(query)
+    }
+
+    pub fn matches(&self, todo: &Todo) -> bool {
+        self.terms.iter().all(|term| term.matches(todo))
+    }
+
+    /// Filters, sorts, limits and finally groups `todos`.
+    pub fn run(&self, todos: &[Todo]) -> Vec<Group> {
+        let mut items: Vec<usize> = (0..todos.len()).filter(|&i| self.matches(&todos[i])).collect();
+
+        // `sort_by` is stable, so ties keep their order in the list.
+        items.sort_by(|&a, &b| {
+            self.sort
+                .iter()
+                .map(|key| key.compare(&todos[a], &todos[b]))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        if let Some(limit) = self.limit {
+            items.truncate(limit);
+        }
+
+        match self.group {
+            None => vec![Group { label: None, items }],
+            Some(by) => group(todos, &items, by),
+        }
+    }
+}
+
+/// Groups keep the order in which their first member appears, with todos
+/// lacking the field collected at the end. A todo with several tags is
+/// listed under each of them.
+fn group(todos: &[Todo], items: &[usize], by: GroupBy) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut unlabelled = Vec::new();
+
+    for &i in items {
+        let labels: Vec<String> = match by {
+            GroupBy::Project => todos[i].project.iter().cloned().collect(),
+            GroupBy::Tag => todos[i].tags.clone(),
+        };
+
+        if labels.is_empty() {
+            unlabelled.push(i);
+        }
+        for label in labels {
+            match groups.iter_mut().find(|g| g.label.as_deref() == Some(label.as_str())) {
+                Some(group) => group.items.push(i),
+                None => groups.push(Group { label: Some(label), items: vec![i] }),
+            }
+        }
+    }
+
+    if !unlabelled.is_empty() {
+        groups.push(Group { label: None, items: unlabelled });
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn sample() -> Vec<Todo> {
+        let mut report = Todo::new("Write report");
+        report.add_tag("work");
+        report.project = Some("q4".to_string());
+        report.priority = Some(Priority::High);
+        report.due = Some(parse_due("2026-10-20").unwrap());
+
+        let mut milk = Todo::new("Buy milk");
+        milk.add_tag("home");
+        milk.priority = Some(Priority::Low);
+        milk.due = Some(parse_due("2026-11-05 09:00").unwrap());
+
+        let mut review = Todo::new("Review PR");
+        review.add_tag("work");
+        review.project = Some("q4".to_string());
+        review.notes = Some("the storage one".to_string());
+        review.complete();
+
+        vec![report, milk, review]
+    }
+
+    fn run(todos: &[Todo], query: &[&str]) -> Vec<usize> {
+        Query::parse(&args(query)).unwrap().run(todos).remove(0).items
+    }
+
+    #[test]
+    fn filters_combine() {
+        let todos = sample();
+        assert_eq!(run(&todos, &["status:open", "tag:work"]), vec![0]);
+        assert_eq!(run(&todos, &["due<2026-11-01"]), vec![0]);
+        assert_eq!(run(&todos, &["priority>=medium"]), vec![0]);
+        assert_eq!(run(&todos, &["-tag:work"]), vec![1]);
+        assert_eq!(run(&todos, &["due:none"]), vec![2]);
+        assert_eq!(run(&todos, &["due:2026-11-05"]), vec![1]);
+        assert_eq!(run(&todos, &["project:Q4", "status:done"]), vec![2]);
+        assert_eq!(run(&todos, &["priority:none"]), vec![2]);
+    }
+
+    #[test]
+    fn free_text_searches_text_and_notes() {
+        let todos = sample();
+        assert_eq!(run(&todos, &["milk"]), vec![1]);
+        assert_eq!(run(&todos, &["STORAGE one"]), vec![2]);
+    }
+
+    #[test]
+    fn sorts_with_missing_values_last() {
+        let todos = sample();
+        assert_eq!(run(&todos, &["--sort", "due"]), vec![0, 1, 2]);
+        assert_eq!(run(&todos, &["--sort", "-due"]), vec![1, 0, 2]);
+        assert_eq!(run(&todos, &["--sort", "priority"]), vec![0, 1, 2]);
+        assert_eq!(run(&todos, &["--sort", "status,text"]), vec![1, 0, 2]);
+        assert_eq!(run(&todos, &["--sort", "text", "--limit", "2"]), vec![1, 2]);
+    }
+
+    #[test]
+    fn groups_by_tag_and_project() {
+        let todos = sample();
+
+        let groups = Query::parse(&args(&["--group", "tag"])).unwrap().run(&todos);
+        let labels: Vec<_> = groups.iter().map(|g| g.label.clone()).collect();
+        assert_eq!(labels, vec![Some("work".to_string()), Some("home".to_string())]);
+        assert_eq!(groups[0].items, vec![0, 2]);
+
+        let groups = Query::parse(&args(&["--group", "project"])).unwrap().run(&todos);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].label, None);
+        assert_eq!(groups[1].items, vec![1]);
+    }
+
+    #[test]
+    fn rejects_bad_queries() {
+        for bad in [&["status:maybe"][..], &["priority>urgent"], &["tag<work"], &["--sort", "size"], &["--limit"]] {
+            assert!(Query::parse(&args(bad)).is_err(), "{:?} should not parse", bad);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+/**
+    Recurring todos.
+
+    Completing a todo that has a `recur` rule adds a fresh copy with the
+    next due date right after it. Schedule-based rules (daily, weekly,
+    monthly) count from the old due date, so finishing late doesn't shift
+    the schedule, but they never produce a date before the day the todo was
+    completed. `after:N` counts from the completion instead, for chores
+    like "water the plants three days after last time".
+*/
+use crate::id::new_id;
+use crate::todo::Todo;
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest `after:N` allowed, about ten years.
+const MAX_AFTER_DAYS: u64 = 3650;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "every", rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    Weekly { days: Vec<Weekday> },
+    Monthly { day: u32 },
+    AfterCompletion { days: u64 },
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    /// `daily`, `weekdays`, `weekly:mon,thu`, `monthly:31` or `after:3`.
+    fn from_str(s: &str) -> Result<Recurrence, String> {
+        let s = s.to_lowercase();
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s.as_str(), None),
+        };
+
+        match (kind, arg) {
+            ("daily", None) => Ok(Recurrence::Daily),
+            ("weekdays", None) => Ok(Recurrence::Weekly {
+                days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+            }),
+            ("weekly", Some(days)) => {
+                let mut days = days
+                    .split(',')
+                    .map(|d| d.trim().parse::<Weekday>().map_err(|_| format!("Unknown weekday: {}", d)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                days.sort_by_key(|d| d.num_days_from_monday());
+                days.dedup();
+                Ok(Recurrence::Weekly { days })
+            }
+            ("monthly", Some(day)) => match day.parse::<u32>() {
+                Ok(day @ 1..=31) => Ok(Recurrence::Monthly { day }),
+                _ => Err(format!("Invalid day of month: {}", day)),
+            },
+            ("after", Some(days)) => match days.trim_end_matches('d').parse::<u64>() {
+                Ok(days @ 1..=MAX_AFTER_DAYS) => Ok(Recurrence::AfterCompletion { days }),
+                _ => Err(format!("Invalid number of days: {} (expected 1 to {})", days, MAX_AFTER_DAYS)),
+            },
+            _ => Err(format!(
+                "Unknown recurrence: {} (expected daily, weekdays, weekly:mon,thu, monthly:N or after:N)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly { days } => {
+                let names: Vec<String> = days.iter().map(|d| d.to_string().to_lowercase()).collect();
+                write!(f, "weekly:{}", names.join(","))
+            }
+            Recurrence::Monthly { day } => write!(f, "monthly:{}", day),
+            Recurrence::AfterCompletion { days } => write!(f, "after:{}", days),
+        }
+    }
+}
+
+impl Recurrence {
+    /**
+        When the next occurrence is due.
+
+        `due` is the due date of the occurrence just completed, if it had
+        one, and `completed` is when it was completed. The time of day of
+        the old due date is kept; without one the new todo is due at
+        midnight.
+
+        `None` if the next date would be past the end of chrono's calendar,
+        which parsing keeps out of reach but a hand-edited file might not.
+    */
+    pub fn next_due(&self, due: Option<NaiveDateTime>, completed: NaiveDateTime) -> Option<NaiveDateTime> {
+        let time = due.map(|d| d.time()).unwrap_or(NaiveTime::MIN);
+        let today = completed.date();
+
+        let date = match self {
+            Recurrence::AfterCompletion { days } => today.checked_add_days(Days::new(*days))?,
+            _ => {
+                // First date after the old due date, but not in the past.
+                let mut date = match due {
+                    Some(due) => self.after(due.date())?,
+                    None => self.after(today)?,
+                };
+                while date < today {
+                    date = self.after(date)?;
+                }
+                date
+            }
+        };
+
+        Some(date.and_time(time))
+    }
+
+    /// The first scheduled date strictly after `date`.
+    fn after(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Recurrence::Daily => date.succ_opt(),
+            Recurrence::Weekly { days } if days.is_empty() => date.checked_add_days(Days::new(7)),
+            Recurrence::Weekly { days } => {
+                let mut next = date.succ_opt()?;
+                while !days.contains(&next.weekday()) {
+                    next = next.succ_opt()?;
+                }
+                Some(next)
+            }
+            Recurrence::Monthly { day } => {
+                let this_month = clamp_to_month(date.year(), date.month(), *day)?;
+                if this_month > date {
+                    Some(this_month)
+                } else if date.month() == 12 {
+                    clamp_to_month(date.year() + 1, 1, *day)
+                } else {
+                    clamp_to_month(date.year(), date.month() + 1, *day)
+                }
+            }
+            Recurrence::AfterCompletion { days } => date.checked_add_days(Days::new(*days)),
+        }
+    }
+}
+
+/// Day `day` of the month, or its last day when the month is shorter,
+/// so `monthly:31` lands on 30 April and 28 or 29 February.
+fn clamp_to_month(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    (1..=day).rev().find_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+}
+
+/// Completes `todos[i]` at `now` and, if it repeats, inserts its next
+/// occurrence right after it and returns a copy.
+///
+/// A todo that's already done is left alone: completing it again would
+/// add a second next occurrence and move its completion time.
+pub fn complete(todos: &mut Vec<Todo>, i: usize, now: NaiveDateTime) -> Option<Todo> {
+    if todos[i].done {
+        return None;
+    }
+    todos[i].complete();
+    let next = next_occurrence(&todos[i], now)?;
+    todos.insert(i + 1, next.clone());
+    Some(next)
+}
+
+/// The todo that replaces `todo` once it's completed at `completed`, if it
+/// repeats and its next date is one chrono can represent.
+pub fn next_occurrence(todo: &Todo, completed: NaiveDateTime) -> Option<Todo> {
+    let rule = todo.recur.as_ref()?;
+    let due = rule.next_due(todo.due, completed)?;
+
+    let mut next = todo.clone();
+    next.id = new_id();
+    next.created_at = Utc::now();
+    next.done = false;
+    next.completed_at = None;
+    next.sessions.clear();
+    next.due = Some(due);
+    // A reminder keeps its distance from the due date.
+    next.remind_at = match (todo.remind_at, todo.due) {
+        (Some(remind_at), Some(old_due)) => due.checked_sub_signed(old_due - remind_at),
+        _ => None,
+    };
+    Some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    /// A next due date, as `next_due` returns it.
+    fn on(s: &str) -> Option<NaiveDateTime> {
+        Some(at(s))
+    }
+
+    fn rule(s: &str) -> Recurrence {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn daily_follows_the_schedule() {
+        let daily = rule("daily");
+        assert_eq!(daily.next_due(Some(at("2026-10-18 09:00")), at("2026-10-18 08:00")), on("2026-10-19 09:00"));
+        // Completed three days late: the next one is today, not in the past.
+        assert_eq!(daily.next_due(Some(at("2026-10-15 09:00")), at("2026-10-18 20:00")), on("2026-10-18 09:00"));
+        assert_eq!(daily.next_due(None, at("2026-10-18 20:00")), on("2026-10-19 00:00"));
+    }
+
+    #[test]
+    fn daily_crosses_leap_day() {
+        let daily = rule("daily");
+        assert_eq!(daily.next_due(Some(at("2028-02-28 09:00")), at("2028-02-28 10:00")), on("2028-02-29 09:00"));
+        assert_eq!(daily.next_due(Some(at("2027-02-28 09:00")), at("2027-02-28 10:00")), on("2027-03-01 09:00"));
+    }
+
+    #[test]
+    fn weekly_picks_next_listed_day() {
+        let weekly = rule("weekly:thu,mon");
+        assert_eq!(weekly, Recurrence::Weekly { days: vec![Weekday::Mon, Weekday::Thu] });
+
+        // 2026-10-19 is a Monday.
+        assert_eq!(weekly.next_due(Some(at("2026-10-19 10:00")), at("2026-10-19 11:00")), on("2026-10-22 10:00"));
+        assert_eq!(weekly.next_due(Some(at("2026-10-22 10:00")), at("2026-10-22 11:00")), on("2026-10-26 10:00"));
+        // Year boundary: Thursday 2026-12-31 to Monday 2027-01-04.
+        assert_eq!(weekly.next_due(Some(at("2026-12-31 10:00")), at("2026-12-31 11:00")), on("2027-01-04 10:00"));
+    }
+
+    #[test]
+    fn monthly_clamps_to_month_end() {
+        let monthly = rule("monthly:31");
+        assert_eq!(monthly.next_due(Some(at("2026-01-31 12:00")), at("2026-01-31 12:00")), on("2026-02-28 12:00"));
+        // The rule remembers day 31, so March gets its full length back.
+        assert_eq!(monthly.next_due(Some(at("2026-02-28 12:00")), at("2026-02-28 12:00")), on("2026-03-31 12:00"));
+        assert_eq!(monthly.next_due(Some(at("2026-03-31 12:00")), at("2026-03-31 12:00")), on("2026-04-30 12:00"));
+        assert_eq!(monthly.next_due(Some(at("2026-12-31 12:00")), at("2026-12-31 12:00")), on("2027-01-31 12:00"));
+    }
+
+    #[test]
+    fn monthly_handles_leap_years() {
+        let monthly = rule("monthly:29");
+        assert_eq!(monthly.next_due(Some(at("2028-01-29 08:00")), at("2028-01-29 09:00")), on("2028-02-29 08:00"));
+        assert_eq!(monthly.next_due(Some(at("2027-01-29 08:00")), at("2027-01-29 09:00")), on("2027-02-28 08:00"));
+        assert_eq!(monthly.next_due(Some(at("2027-02-28 08:00")), at("2027-02-28 09:00")), on("2027-03-29 08:00"));
+    }
+
+    #[test]
+    fn monthly_without_due_uses_this_month_if_still_ahead() {
+        let monthly = rule("monthly:15");
+        assert_eq!(monthly.next_due(None, at("2026-10-10 09:00")), on("2026-10-15 00:00"));
+        assert_eq!(monthly.next_due(None, at("2026-10-15 09:00")), on("2026-11-15 00:00"));
+    }
+
+    #[test]
+    fn after_completion_counts_from_completion() {
+        let after = rule("after:3");
+        assert_eq!(after.next_due(Some(at("2026-10-01 18:00")), at("2026-10-18 09:00")), on("2026-10-21 18:00"));
+        assert_eq!(after.next_due(None, at("2026-02-27 09:00")), on("2026-03-02 00:00"));
+    }
+
+    #[test]
+    fn parses_and_displays() {
+        for s in ["daily", "weekly:mon,fri", "monthly:31", "after:10"] {
+            assert_eq!(rule(s).to_string(), s);
+        }
+        assert_eq!(rule("weekdays").to_string(), "weekly:mon,tue,wed,thu,fri");
+        assert!("monthly:32".parse::<Recurrence>().is_err());
+        assert!("after:0".parse::<Recurrence>().is_err());
+        assert!("after:99999999".parse::<Recurrence>().is_err());
+        assert!("weekly:funday".parse::<Recurrence>().is_err());
+        assert!("hourly".parse::<Recurrence>().is_err());
+    }
+
+    #[test]
+    fn next_occurrence_is_a_fresh_open_todo() {
+        let mut todo = Todo::new("Take out bins");
+        todo.recur = Some(rule("weekly:tue"));
+        todo.due = Some(at("2026-10-20 07:00"));
+        todo.remind_at = Some(at("2026-10-19 21:00"));
+        todo.add_tag("home");
+        todo.complete();
+
+        let next = next_occurrence(&todo, at("2026-10-20 07:30")).unwrap();
+        assert_ne!(next.id, todo.id);
+        assert!(!next.done);
+        assert!(next.completed_at.is_none());
+        assert_eq!(next.due, on("2026-10-27 07:00"));
+        assert_eq!(next.remind_at, on("2026-10-26 21:00"));
+        assert_eq!(next.tags, todo.tags);
+        assert_eq!(next.recur, todo.recur);
+
+        assert!(next_occurrence(&Todo::new("one-off"), at("2026-10-20 07:30")).is_none());
+    }
+
+    #[test]
+    fn completing_twice_adds_one_occurrence() {
+        let mut todo = Todo::new("Take out bins");
+        todo.recur = Some(rule("weekly:tue"));
+        todo.due = Some(at("2026-10-20 07:00"));
+        let mut todos = vec![todo];
+
+        let next = complete(&mut todos, 0, at("2026-10-20 07:30")).unwrap();
+        let completed_at = todos[0].completed_at;
+        assert!(complete(&mut todos, 0, at("2026-10-21 09:00")).is_none());
+
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[1].id, next.id);
+        assert_eq!(todos[0].completed_at, completed_at);
+    }
+
+    #[test]
+    fn a_date_past_the_calendar_ends_the_series() {
+        // Only a hand-edited file can have a rule this long.
+        let mut todo = Todo::new("Water the plants");
+        todo.recur = Some(Recurrence::AfterCompletion { days: u64::MAX });
+        let mut todos = vec![todo];
+
+        assert!(complete(&mut todos, 0, at("2026-10-20 07:30")).is_none());
+        assert!(todos[0].done);
+        assert_eq!(todos.len(), 1);
+        assert_eq!(rule("daily").next_due(None, NaiveDate::MAX.and_time(NaiveTime::MIN)), None);
+    }
+}
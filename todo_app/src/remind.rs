@@ -0,0 +1,404 @@
+/**
+    Reminders sent by `todo daemon`.
+
+    Every open todo can produce two: one at its `remind_at` time, and one
+    when it falls due (or comes within the look-ahead of falling due). Each
+    is sent once. What has been sent is kept in `<list>.reminders.json`,
+    keyed by the time it was for, so moving a due date or reminder arms it
+    again while restarting the daemon doesn't repeat anything.
+*/
+use crate::todo::{format_due, Todo};
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+/// How long a webhook gets to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// The furthest ahead `todo daemon --ahead` may look, a year in minutes.
+pub const MAX_AHEAD_MINUTES: i64 = 366 * 24 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Reminder,
+    DueSoon,
+    Overdue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reminder {
+    pub id: String,
+    pub text: String,
+    pub kind: Kind,
+    /// When the todo is due, or the reminder was set for.
+    pub at: NaiveDateTime,
+}
+
+impl Reminder {
+    pub fn title(&self) -> &'static str {
+        match self.kind {
+            Kind::Reminder => "Reminder",
+            Kind::DueSoon => "Due soon",
+            Kind::Overdue => "Overdue",
+        }
+    }
+
+    pub fn body(&self) -> String {
+        match self.kind {
+            Kind::Reminder => self.text.clone(),
+            Kind::DueSoon | Kind::Overdue => format!("{} (due {})", self.text, format_due(&self.at)),
+        }
+    }
+
+    /// Identifies what the reminder is about, including the time, so that a
+    /// rescheduled todo gets reminded again.
+    fn key(&self) -> String {
+        let kind = if self.kind == Kind::Reminder { "remind" } else { "due" };
+        format!("{} {} {}", self.id, kind, self.at.format("%Y-%m-%dT%H:%M"))
+    }
+}
+
+/// The reminders that are due at `now` and haven't been sent yet.
+/// `ahead` is how long before a due date to send its reminder.
+pub fn pending(todos: &[Todo], sent: &Sent, now: NaiveDateTime, ahead: TimeDelta) -> Vec<Reminder> {
+    let mut reminders = Vec::new();
+    for todo in todos.iter().filter(|todo| !todo.done) {
+        let reminder = |kind, at| Reminder { id: todo.id.clone(), text: todo.text.clone(), kind, at };
+        if let Some(at) = todo.remind_at.filter(|&at| at <= now) {
+            reminders.push(reminder(Kind::Reminder, at));
+        }
+        // A window reaching back before the calendar starts has certainly begun.
+        let window_started = |due: NaiveDateTime| due.checked_sub_signed(ahead).is_none_or(|start| start <= now);
+        if let Some(due) = todo.due.filter(|&due| window_started(due)) {
+            reminders.push(reminder(if due <= now { Kind::Overdue } else { Kind::DueSoon }, due));
+        }
+    }
+    reminders.retain(|reminder| !sent.keys.contains(&reminder.key()));
+    reminders
+}
+
+/// Reminders already sent, kept next to the list.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Sent {
+    keys: BTreeSet<String>,
+}
+
+impl Sent {
+    pub fn load(path: &Path) -> Result<Sent, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Sent::default());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(|e| format!("{} is corrupt: {}", path.display(), e).into())
+    }
+
+    /// Written to a temporary file and renamed into place, like the journal.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn mark(&mut self, reminder: &Reminder) {
+        self.keys.insert(reminder.key());
+    }
+
+    /// Forgets reminders for todos that are done or gone, so the file
+    /// doesn't grow forever. Reopening a todo reminds about it again.
+    pub fn prune(&mut self, todos: &[Todo]) {
+        self.keys.retain(|key| {
+            let id = key.split(' ').next().unwrap_or_default();
+            todos.iter().any(|todo| todo.id == id && !todo.done)
+        });
+    }
+}
+
+/// Somewhere reminders can be delivered.
+pub trait Notifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Box<dyn Error>>;
+}
+
+/// Prints reminders, for running the daemon in a terminal or under a
+/// service manager that keeps its output.
+pub struct Stdout;
+
+impl Notifier for Stdout {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Box<dyn Error>> {
+        println!("{}: {}", reminder.title(), reminder.body());
+        Ok(())
+    }
+}
+
+/// Runs a program with the title and body as its last two arguments,
+/// e.g. `notify-send` for desktop notifications.
+pub struct RunCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Notifier for RunCommand {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Box<dyn Error>> {
+        let status = Command::new(&self.program)
+            .args(&self.args)
+            .arg(reminder.title())
+            .arg(reminder.body())
+            .status()
+            .map_err(|e| format!("Could not run {}: {}", self.program, e))?;
+        if !status.success() {
+            return Err(format!("{} failed with {}", self.program, status).into());
+        }
+        Ok(())
+    }
+}
+
+/// POSTs each reminder as JSON to an `http://` URL.
+pub struct Webhook {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Webhook, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("Unsupported webhook URL: {} (only http:// is supported)", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(at) => (&rest[..at], &rest[at..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| format!("Invalid port in {}", url))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("Missing host in {}", url));
+        }
+        Ok(Webhook { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+impl Notifier for Webhook {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Box<dyn Error>> {
+        let body = serde_json::json!({
+            "id": reminder.id,
+            "text": reminder.text,
+            "title": reminder.title(),
+            "message": reminder.body(),
+            "at": reminder.at.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        })
+        .to_string();
+
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+        stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            body.len(),
+            body
+        )?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let status = response.lines().next().unwrap_or_default();
+        match status.split(' ').nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(format!("Webhook answered {:?}", status).into()),
+        }
+    }
+}
+
+/// Picks a notifier from `stdout`, `command:PROGRAM [ARG]...` or
+/// `webhook:http://...`.
+pub fn notifier(spec: &str) -> Result<Box<dyn Notifier>, String> {
+    match spec.split_once(':') {
+        None if spec == "stdout" => Ok(Box::new(Stdout)),
+        Some(("command", command)) => {
+            let mut words = command.split_whitespace().map(String::from);
+            let program = words.next().ok_or("Missing program for command notifier")?;
+            Ok(Box::new(RunCommand { program, args: words.collect() }))
+        }
+        Some(("webhook", url)) => Ok(Box::new(Webhook::new(url)?)),
+        _ => Err(format!("Unknown notifier: {} (expected stdout, command:PROGRAM or webhook:URL)", spec)),
+    }
+}
+
+/**
+    Sends whatever is pending and remembers it in `sent_path`. Returns how
+    many reminders went out.
+
+    A reminder that fails to send isn't marked, so the next check tries it
+    again; the others still go out.
+*/
+pub fn send_pending(
+    todos: &[Todo],
+    sent_path: &Path,
+    notifier: &dyn Notifier,
+    now: NaiveDateTime,
+    ahead: TimeDelta,
+) -> Result<usize, Box<dyn Error>> {
+    let mut sent = Sent::load(sent_path)?;
+    sent.prune(todos);
+
+    let mut count = 0;
+    for reminder in pending(todos, &sent, now, ahead) {
+        match notifier.notify(&reminder) {
+            Ok(()) => {
+                sent.mark(&reminder);
+                count += 1;
+            }
+            Err(e) => eprintln!("Warning: could not send reminder for \"{}\": {}", reminder.text, e),
+        }
+    }
+
+    sent.save(sent_path)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn overdue(text: &str) -> Reminder {
+        Reminder { id: "01a".into(), text: text.into(), kind: Kind::Overdue, at: at("2026-10-19 09:00") }
+    }
+
+    /// Keeps what it was asked to send.
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<String>>);
+
+    impl Notifier for Recorder {
+        fn notify(&self, reminder: &Reminder) -> Result<(), Box<dyn Error>> {
+            self.0.borrow_mut().push(format!("{}: {}", reminder.title(), reminder.body()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finds_due_and_upcoming_todos() {
+        let mut todos = vec![Todo::new("call"), Todo::new("pay"), Todo::new("later"), Todo::new("done")];
+        todos[0].remind_at = Some(at("2026-10-19 09:00"));
+        todos[1].due = Some(at("2026-10-19 09:10"));
+        todos[2].due = Some(at("2026-10-19 12:00"));
+        todos[3].due = Some(at("2026-10-19 08:00"));
+        todos[3].complete();
+
+        let now = at("2026-10-19 09:00");
+        let kinds: Vec<(String, Kind)> = pending(&todos, &Sent::default(), now, TimeDelta::minutes(15))
+            .into_iter()
+            .map(|r| (r.text, r.kind))
+            .collect();
+        assert_eq!(kinds, vec![("call".to_string(), Kind::Reminder), ("pay".to_string(), Kind::DueSoon)]);
+    }
+
+    #[test]
+    fn a_look_ahead_past_the_calendar_is_no_panic() {
+        let mut todos = vec![Todo::new("ancient"), Todo::new("later")];
+        todos[0].due = Some(NaiveDateTime::MIN);
+        todos[1].due = Some(at("2026-10-19 12:00"));
+
+        let ahead = TimeDelta::try_minutes(MAX_AHEAD_MINUTES).unwrap();
+        let reminders = pending(&todos, &Sent::default(), at("2026-10-19 09:00"), ahead);
+        let kinds: Vec<Kind> = reminders.into_iter().map(|r| r.kind).collect();
+        assert_eq!(kinds, vec![Kind::Overdue, Kind::DueSoon]);
+    }
+
+    #[test]
+    fn each_reminder_is_sent_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let sent_path = dir.path().join("todo.reminders.json");
+        let mut todos = vec![Todo::new("pay rent")];
+        todos[0].due = Some(at("2026-10-19 09:00"));
+        let recorder = Recorder::default();
+        let send = |todos: &[Todo], now| {
+            send_pending(todos, &sent_path, &recorder, at(now), TimeDelta::zero()).unwrap()
+        };
+
+        assert_eq!(send(&todos, "2026-10-19 08:59"), 0);
+        assert_eq!(send(&todos, "2026-10-19 09:00"), 1);
+        assert_eq!(send(&todos, "2026-10-19 09:30"), 0);
+
+        // Moving the due date arms it again.
+        todos[0].due = Some(at("2026-10-19 10:00"));
+        assert_eq!(send(&todos, "2026-10-19 10:05"), 1);
+
+        assert_eq!(
+            recorder.0.into_inner(),
+            vec!["Overdue: pay rent (due 2026-10-19 09:00)", "Overdue: pay rent (due 2026-10-19 10:00)"]
+        );
+    }
+
+    #[test]
+    fn posts_to_a_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/todo", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = Vec::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader.get_mut().write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            (head, String::from_utf8(body).unwrap())
+        });
+
+        let notifier = notifier(&format!("webhook:{}", url)).unwrap();
+        notifier.notify(&overdue("pay")).unwrap();
+
+        let (head, body) = server.join().unwrap();
+        assert_eq!(head[0], "POST /hooks/todo HTTP/1.1\r\n");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["title"], "Overdue");
+        assert_eq!(json["message"], "pay (due 2026-10-19 09:00)");
+    }
+
+    #[test]
+    fn parses_notifiers() {
+        for good in ["stdout", "command:notify-send -u critical", "webhook:http://localhost:8080"] {
+            assert!(notifier(good).is_ok(), "{}", good);
+        }
+        for bad in ["email", "command:", "webhook:https://example.com", "webhook:http://:80"] {
+            assert!(notifier(bad).is_err(), "{}", bad);
+        }
+        let failing = RunCommand { program: "false".into(), args: Vec::new() };
+        assert!(failing.notify(&overdue("pay")).is_err());
+    }
+}
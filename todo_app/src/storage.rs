@@ -0,0 +1,529 @@
+mod git;
+mod json;
+mod location;
+mod sqlite;
+
+pub use git::Report as SyncReport;
+pub use json::JsonStore;
+pub use location::{current_list, named_lists, select_list, List, DEFAULT_LIST, PROJECT_LIST};
+pub use sqlite::SqliteStore;
+
+use crate::journal::Journal;
+use git::Repo;
+use crate::todo::Todo;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a command waits for another one to finish before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCK_RETRY: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Backend::Json),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(format!("Unknown storage backend: {} (expected json or sqlite)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::Json => write!(f, "json"),
+            Backend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/**
+    Somewhere a todo list can be loaded from and saved to.
+
+    Implementations only need to make `save` atomic; locking across
+    processes is handled once, in `update_with`, for every backend.
+*/
+pub trait Storage {
+    fn backend(&self) -> Backend;
+    fn path(&self) -> &Path;
+    fn load(&self) -> Result<Vec<Todo>, StorageError>;
+    fn save(&self, todos: &[Todo]) -> Result<(), StorageError>;
+}
+
+/**
+    Everything that can go wrong reading or writing the todo list.
+
+    A `Corrupt` file is never overwritten: every command loads before it
+    saves, so a parse error stops the command before anything touches the
+    disk. The user can fix the file by hand or restore one of the backups.
+*/
+#[derive(Debug)]
+pub enum StorageError {
+    Io { path: PathBuf, source: io::Error },
+    Corrupt { path: PathBuf, line: usize, column: usize, message: String },
+    Unsupported { path: PathBuf, message: String },
+    Locked { path: PathBuf, waited: Duration },
+    Database { path: PathBuf, message: String },
+    Git { path: PathBuf, message: String },
+    NoDataDir,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            StorageError::Corrupt { path, line, column, message } => write!(
+                f,
+                "{} is corrupt at line {}, column {}: {}\n\
+                 The file was left untouched. Fix it by hand or restore {}.bak.1",
+                path.display(),
+                line,
+                column,
+                message,
+                path.display()
+            ),
+            StorageError::Unsupported { path, message } => write!(f, "{}: {}", path.display(), message),
+            StorageError::Locked { path, waited } => write!(
+                f,
+                "{} is locked by another todo command; gave up after {:.1}s",
+                path.display(),
+                waited.as_secs_f64()
+            ),
+            StorageError::Database { path, message } => write!(f, "{}: {}", path.display(), message),
+            StorageError::Git { path, message } => write!(f, "{}: git: {}", path.display(), message),
+            StorageError::NoDataDir => write!(
+                f,
+                "Can't tell where to keep todo lists: set XDG_DATA_HOME or HOME, or run todo init for a project list"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> StorageError + '_ {
+    move |source| StorageError::Io { path: path.to_path_buf(), source }
+}
+
+fn corrupt(path: &Path, e: serde_json::Error) -> StorageError {
+    StorageError::Corrupt {
+        path: path.to_path_buf(),
+        line: e.line(),
+        column: e.column(),
+        message: e.to_string(),
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub fn open(list: &List, backend: Backend) -> Box<dyn Storage> {
+    match backend {
+        Backend::Json => Box::new(JsonStore::new(list.file(".json"))),
+        Backend::Sqlite => Box::new(SqliteStore::new(list.file(".db"))),
+    }
+}
+
+/// The current list's SQLite database once `todo migrate --to sqlite` has
+/// created it, otherwise its JSON file. The data directory is created on
+/// first use.
+pub fn open_default() -> Result<Box<dyn Storage>, StorageError> {
+    Ok(open_existing(&prepare_list()?))
+}
+
+fn prepare_list() -> Result<List, StorageError> {
+    let list = current_list()?;
+    if let Some(dir) = list.base.parent().filter(|_| list.name.is_some()) {
+        fs::create_dir_all(dir).map_err(io_error(dir))?;
+    }
+    Ok(list)
+}
+
+fn open_existing(list: &List) -> Box<dyn Storage> {
+    if list.file(".db").exists() {
+        open(list, Backend::Sqlite)
+    } else {
+        open(list, Backend::Json)
+    }
+}
+
+/**
+    Locks the list as a whole, on `<list>.lock`, whichever backend it's in.
+
+    The lock on a store's own file can't cover choosing the store: a
+    command that picked the JSON file and then waited on its lock while
+    `todo migrate` ran would wake up to a file renamed away, load it as an
+    empty list and save it, and the new database would hide the change.
+    Commands that change the list take this lock first and only then look
+    at which backend it's in.
+*/
+fn lock_list(list: &List) -> Result<FileLock, StorageError> {
+    FileLock::acquire(&list.base, LOCK_TIMEOUT)
+}
+
+pub fn load_todos() -> Result<Vec<Todo>, StorageError> {
+    open_default()?.load()
+}
+
+/**
+    Runs one read-modify-write cycle on the todo list.
+
+    Without the lock two commands started together both load the same list,
+    each adds its item, and whichever saves last silently drops the other's.
+    Commands that only read don't need the lock: every backend saves
+    atomically, so a reader always sees a complete version.
+
+    `change` can return an error to abandon the update without saving.
+    Successful changes are recorded in the journal under `description` so
+    that `undo` can revert them.
+*/
+pub fn update<T, F>(description: &str, change: F) -> Result<T, Box<dyn Error>>
+where
+    F: FnOnce(&mut Vec<Todo>) -> Result<T, Box<dyn Error>>,
+{
+    update_list(&prepare_list()?, description, change)
+}
+
+fn update_list<T, F>(list: &List, description: &str, change: F) -> Result<T, Box<dyn Error>>
+where
+    F: FnOnce(&mut Vec<Todo>) -> Result<T, Box<dyn Error>>,
+{
+    let _lock = lock_list(list)?;
+    update_with(open_existing(list).as_ref(), LOCK_TIMEOUT, description, change)
+}
+
+pub fn update_with<T, F>(
+    store: &dyn Storage,
+    timeout: Duration,
+    description: &str,
+    change: F,
+) -> Result<T, Box<dyn Error>>
+where
+    F: FnOnce(&mut Vec<Todo>) -> Result<T, Box<dyn Error>>,
+{
+    let _lock = FileLock::acquire(store.path(), timeout)?;
+
+    let mut todos = store.load()?;
+    let before = todos.clone();
+    let result = change(&mut todos)?;
+    store.save(&todos)?;
+    record_change(store, description, &before, &todos)?;
+
+    Ok(result)
+}
+
+/// Records a saved change in the journal and, if the list is synced, in
+/// its repository.
+fn record_change(
+    store: &dyn Storage,
+    description: &str,
+    before: &[Todo],
+    after: &[Todo],
+) -> Result<(), Box<dyn Error>> {
+    let journal_path = sibling(store.path(), ".journal.json");
+    let mut journal = Journal::load(&journal_path)?;
+    journal.record(description, before, after);
+    journal.save(&journal_path)?;
+
+    commit_to_sync(store, after, description);
+    Ok(())
+}
+
+/// The change is already saved, so a failed commit only warns: the next
+/// `todo sync` commits whatever is left over.
+fn commit_to_sync(store: &dyn Storage, todos: &[Todo], message: &str) {
+    let repo = Repo::for_list(store.path());
+    if repo.exists()
+        && let Err(e) = repo.commit(todos, message)
+    {
+        eprintln!("Warning: could not commit to {}: {}", repo.dir().display(), e);
+    }
+}
+
+/// Reverts the last `count` changes. Returns their descriptions.
+pub fn undo(count: usize) -> Result<Vec<String>, Box<dyn Error>> {
+    let list = prepare_list()?;
+    let _lock = lock_list(&list)?;
+    replay(open_existing(&list).as_ref(), |journal, todos| journal.undo(todos, count))
+}
+
+/// Re-applies the last `count` undone changes. Returns their descriptions.
+pub fn redo(count: usize) -> Result<Vec<String>, Box<dyn Error>> {
+    let list = prepare_list()?;
+    let _lock = lock_list(&list)?;
+    replay(open_existing(&list).as_ref(), |journal, todos| journal.redo(todos, count))
+}
+
+fn replay<F>(store: &dyn Storage, step: F) -> Result<Vec<String>, Box<dyn Error>>
+where
+    F: FnOnce(&mut Journal, &mut Vec<Todo>) -> Result<Vec<String>, Box<dyn Error>>,
+{
+    let _lock = FileLock::acquire(store.path(), LOCK_TIMEOUT)?;
+
+    let journal_path = sibling(store.path(), ".journal.json");
+    let mut journal = Journal::load(&journal_path)?;
+    let mut todos = store.load()?;
+
+    let descriptions = step(&mut journal, &mut todos)?;
+    if !descriptions.is_empty() {
+        store.save(&todos)?;
+        journal.save(&journal_path)?;
+        commit_to_sync(store, &todos, &descriptions.join(", "));
+    }
+
+    Ok(descriptions)
+}
+
+/// Starts syncing the current list through a git repository next to it.
+/// Returns the repository and what was merged from `remote`.
+pub fn start_sync(remote: Option<&str>) -> Result<(PathBuf, SyncReport), Box<dyn Error>> {
+    let list = prepare_list()?;
+    let _lock = lock_list(&list)?;
+    let store = open_existing(&list);
+    let report = git::init(store.as_ref(), remote)?;
+    Ok((Repo::for_list(store.path()).dir().to_path_buf(), report))
+}
+
+pub fn sync() -> Result<SyncReport, Box<dyn Error>> {
+    let list = prepare_list()?;
+    let _lock = lock_list(&list)?;
+    git::sync(open_existing(&list).as_ref())
+}
+
+/**
+    Copies the current list into the `to` backend and retires the old one.
+
+    The old file is renamed to `<name>.migrated` rather than deleted, so
+    `open_default` stops picking it up but nothing is lost if the new
+    backend turns out not to suit. The list and both files stay locked
+    throughout.
+*/
+pub fn migrate_to(to: Backend) -> Result<usize, Box<dyn Error>> {
+    let list = prepare_list()?;
+    let _lock = lock_list(&list)?;
+    let from = open_existing(&list);
+    if from.backend() == to {
+        return Err(format!("Already using the {} backend", to).into());
+    }
+
+    migrate_between(from.as_ref(), open(&list, to).as_ref())
+}
+
+fn migrate_between(from: &dyn Storage, to: &dyn Storage) -> Result<usize, Box<dyn Error>> {
+    let _from_lock = FileLock::acquire(from.path(), LOCK_TIMEOUT)?;
+    let _to_lock = FileLock::acquire(to.path(), LOCK_TIMEOUT)?;
+
+    let todos = from.load()?;
+    to.save(&todos)?;
+
+    if to.load()? != todos {
+        return Err(format!("{} does not match {} after migrating", to.path().display(), from.path().display()).into());
+    }
+
+    if from.path().exists() {
+        let retired = sibling(from.path(), ".migrated");
+        fs::rename(from.path(), &retired).map_err(io_error(from.path()))?;
+    }
+
+    Ok(todos.len())
+}
+
+/**
+    Advisory lock on `<file>.lock`, released when dropped.
+
+    The lock lives on a separate file because `JsonStore` renames a fresh
+    file over the list, and a lock on the old inode would protect nothing.
+    The OS drops the lock when the process exits, so a crashed command never
+    leaves the list locked.
+*/
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    pub fn acquire(path: &Path, timeout: Duration) -> Result<FileLock, StorageError> {
+        let lock_path = sibling(path, ".lock");
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(io_error(&lock_path))?;
+
+        let start = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(FileLock { _file: file }),
+                Err(TryLockError::WouldBlock) if start.elapsed() < timeout => thread::sleep(LOCK_RETRY),
+                Err(TryLockError::WouldBlock) => {
+                    return Err(StorageError::Locked { path: path.to_path_buf(), waited: start.elapsed() });
+                }
+                Err(TryLockError::Error(source)) => return Err(StorageError::Io { path: lock_path, source }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_times_out_while_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+
+        let _held = FileLock::acquire(store.path(), LOCK_TIMEOUT).unwrap();
+        let result = update_with(&store, Duration::from_millis(100), "add", |todos| {
+            todos.push(Todo::new("never saved"));
+            Ok(())
+        });
+
+        let err = result.unwrap_err();
+        assert!(matches!(err.downcast_ref::<StorageError>(), Some(StorageError::Locked { .. })));
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    fn concurrent_writers_lose_nothing(store: fn(&Path) -> Box<dyn Storage>) {
+        const WRITERS: usize = 16;
+        const ADDS: usize = 10;
+
+        let dir = tempfile::tempdir().unwrap();
+
+        // Each writer opens its own store and lock file handle, exactly as
+        // separate processes would, so they contend for the same OS-level lock.
+        let handles: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let dir = dir.path().to_path_buf();
+                thread::spawn(move || {
+                    let store = store(&dir);
+                    for a in 0..ADDS {
+                        update_with(store.as_ref(), Duration::from_secs(30), "add", |todos| {
+                            todos.push(Todo::new(&format!("{}-{}", w, a)));
+                            Ok(())
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut texts: Vec<String> = store(dir.path()).load().unwrap().into_iter().map(|t| t.text).collect();
+        texts.sort();
+        let mut expected: Vec<String> = (0..WRITERS)
+            .flat_map(|w| (0..ADDS).map(move |a| format!("{}-{}", w, a)))
+            .collect();
+        expected.sort();
+
+        assert_eq!(texts, expected);
+    }
+
+    #[test]
+    fn concurrent_json_writers_lose_nothing() {
+        concurrent_writers_lose_nothing(|dir| Box::new(JsonStore::new(dir.join("todo.json"))));
+    }
+
+    #[test]
+    fn concurrent_sqlite_writers_lose_nothing() {
+        concurrent_writers_lose_nothing(|dir| Box::new(SqliteStore::new(dir.join("todo.db"))));
+    }
+
+    #[test]
+    fn migrates_json_to_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = JsonStore::new(dir.path().join("todo.json"));
+        let sqlite = SqliteStore::new(dir.path().join("todo.db"));
+
+        let mut todos = vec![Todo::new("one"), Todo::new("two")];
+        todos[0].complete();
+        json.save(&todos).unwrap();
+
+        assert_eq!(migrate_between(&json, &sqlite).unwrap(), 2);
+        assert_eq!(sqlite.load().unwrap(), todos);
+        assert!(!json.path().exists());
+        assert!(sibling(json.path(), ".migrated").exists());
+    }
+
+    #[test]
+    fn an_update_waiting_on_a_migration_lands_in_the_new_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = List { name: None, base: dir.path().join("todo") };
+        let json = JsonStore::new(list.file(".json"));
+        json.save(&[Todo::new("one")]).unwrap();
+
+        // migrate_to's locks, with an update started while they're held.
+        let list_lock = lock_list(&list).unwrap();
+        let json_lock = FileLock::acquire(json.path(), LOCK_TIMEOUT).unwrap();
+        let waiting = {
+            let list = list.clone();
+            thread::spawn(move || {
+                update_list(&list, "add", |todos| {
+                    todos.push(Todo::new("two"));
+                    Ok(())
+                })
+                .unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+
+        // What migrate_between does under them.
+        SqliteStore::new(list.file(".db")).save(&json.load().unwrap()).unwrap();
+        fs::rename(json.path(), sibling(json.path(), ".migrated")).unwrap();
+        drop((json_lock, list_lock));
+        waiting.join().unwrap();
+
+        let texts: Vec<String> = open_existing(&list).load().unwrap().into_iter().map(|t| t.text).collect();
+        assert_eq!(texts, vec!["one", "two"]);
+        assert!(!list.file(".json").exists());
+    }
+
+    #[test]
+    fn undo_reverts_recorded_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+
+        for text in ["one", "two"] {
+            update_with(&store, LOCK_TIMEOUT, text, |todos| {
+                todos.push(Todo::new(text));
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        let undone = replay(&store, |journal, todos| journal.undo(todos, 1)).unwrap();
+        assert_eq!(undone, vec!["two"]);
+        assert_eq!(store.load().unwrap().len(), 1);
+
+        let redone = replay(&store, |journal, todos| journal.redo(todos, 1)).unwrap();
+        assert_eq!(redone, vec!["two"]);
+        assert_eq!(store.load().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,372 @@
+use super::{io_error, record_change, FileLock, Storage, StorageError, LOCK_TIMEOUT};
+use crate::merge::{merge, Conflict};
+use crate::todo::Todo;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The list's file inside the repository.
+const FILE: &str = "todo.json";
+const REMOTE: &str = "origin";
+const BRANCH: &str = "main";
+
+/// How many times `sync` merges again when someone else pushed in between.
+const PUSH_ATTEMPTS: usize = 3;
+
+/**
+    A git repository holding a copy of one list, `<list>.sync/todo.json`,
+    whichever backend the list itself uses.
+
+    Every change to a synced list is committed, so the history of the
+    repository is the history of the list. Commits only ever add to the
+    branch; `sync` merges what other machines pushed item by item (see
+    `merge`) instead of letting git merge the JSON as text.
+*/
+pub struct Repo {
+    dir: PathBuf,
+}
+
+/// What `sync` did.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Todos other machines added, changed or removed.
+    pub received: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Repo {
+    pub fn for_list(path: &Path) -> Repo {
+        Repo { dir: path.with_extension("sync") }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn exists(&self) -> bool {
+        self.dir.join(".git").exists()
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output, StorageError> {
+        Command::new("git").arg("-C").arg(&self.dir).args(args).output().map_err(|e| {
+            let message = match e.kind() {
+                io::ErrorKind::NotFound => "git is not installed".to_string(),
+                _ => e.to_string(),
+            };
+            StorageError::Git { path: self.dir.clone(), message }
+        })
+    }
+
+    /// Runs a git command and returns its output, failing if git does.
+    fn git(&self, args: &[&str]) -> Result<String, StorageError> {
+        let output = self.run(args)?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(StorageError::Git { path: self.dir.clone(), message });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+    }
+
+    /// Runs a git command that answers a yes/no question with its exit code.
+    fn check(&self, args: &[&str]) -> Result<bool, StorageError> {
+        Ok(self.run(args)?.status.success())
+    }
+
+    fn create(&self, remote: Option<&str>) -> Result<(), StorageError> {
+        fs::create_dir_all(&self.dir).map_err(io_error(&self.dir))?;
+        self.git(&["init", "-q", "-b", BRANCH])?;
+
+        // Commits need an author even on machines where git was never set up.
+        if !self.check(&["config", "user.email"])? {
+            self.git(&["config", "user.name", "todo"])?;
+            self.git(&["config", "user.email", "todo@localhost"])?;
+        }
+        if let Some(remote) = remote {
+            self.git(&["remote", "add", REMOTE, remote])?;
+        }
+        Ok(())
+    }
+
+    /// Writes `todos` into the work tree and stages them.
+    fn stage(&self, todos: &[Todo]) -> Result<(), StorageError> {
+        let path = self.dir.join(FILE);
+        let mut contents = serde_json::to_string_pretty(todos).expect("todos always serialize");
+        contents.push('\n');
+        fs::write(&path, contents).map_err(io_error(&path))?;
+        self.git(&["add", FILE])?;
+        Ok(())
+    }
+
+    /// Commits `todos` unless they're what the last commit already has.
+    pub fn commit(&self, todos: &[Todo], message: &str) -> Result<(), StorageError> {
+        self.stage(todos)?;
+        if !self.check(&["diff", "--cached", "--quiet"])? {
+            self.git(&["commit", "-q", "-m", message])?;
+        }
+        Ok(())
+    }
+
+    fn read(&self, rev: &str) -> Result<Vec<Todo>, StorageError> {
+        let contents = self.git(&["show", &format!("{}:{}", rev, FILE)])?;
+        serde_json::from_str(&contents).map_err(|e| StorageError::Git {
+            path: self.dir.clone(),
+            message: format!("{} in {} is corrupt: {}", FILE, rev, e),
+        })
+    }
+
+    /// Fetches the remote branch, if it has been pushed yet. Returns its commit.
+    fn fetch(&self) -> Result<Option<String>, StorageError> {
+        let heads = self.git(&["ls-remote", "--heads", REMOTE, BRANCH])?;
+        if heads.is_empty() {
+            return Ok(None);
+        }
+        self.git(&["fetch", "-q", REMOTE, BRANCH])?;
+        self.git(&["rev-parse", "FETCH_HEAD"]).map(Some)
+    }
+
+    /**
+        Brings `theirs` into the branch and returns the merged list, or
+        `None` if the branch already has everything in it.
+
+        When one side is simply ahead of the other the branch fast-forwards.
+        Otherwise the merge commit is made with `-s ours`, which records
+        both parents without git touching the file, and then the file is
+        replaced with the result of the per-item merge.
+    */
+    fn merge(&self, theirs: &str, report: &mut Report) -> Result<Option<Vec<Todo>>, StorageError> {
+        if self.check(&["merge-base", "--is-ancestor", theirs, "HEAD"])? {
+            return Ok(None);
+        }
+        if self.check(&["merge-base", "--is-ancestor", "HEAD", theirs])? {
+            self.git(&["merge", "-q", "--ff-only", theirs])?;
+            return self.read("HEAD").map(Some);
+        }
+
+        let base = match self.git(&["merge-base", "HEAD", theirs]) {
+            Ok(base) => self.read(&base)?,
+            // Two machines that each started syncing their own list.
+            Err(_) => Vec::new(),
+        };
+        let merged = merge(&base, &self.read("HEAD")?, &self.read(theirs)?);
+        report.conflicts.extend(merged.conflicts);
+
+        self.git(&["merge", "-q", "--no-commit", "-s", "ours", "--allow-unrelated-histories", theirs])?;
+        self.stage(&merged.todos)?;
+        self.git(&["commit", "-q", "-m", &format!("Merge todos from {}", REMOTE)])?;
+        Ok(Some(merged.todos))
+    }
+}
+
+/// Starts keeping `store`'s list in a git repository, pushing to `remote`.
+/// If the remote already has a list, the two are merged.
+pub fn init(store: &dyn Storage, remote: Option<&str>) -> Result<Report, Box<dyn Error>> {
+    let repo = Repo::for_list(store.path());
+    if repo.exists() {
+        return Err(format!("This list is already synced through {}", repo.dir.display()).into());
+    }
+
+    {
+        let _lock = FileLock::acquire(store.path(), LOCK_TIMEOUT)?;
+        repo.create(remote)?;
+        repo.commit(&store.load()?, "Start syncing")?;
+    }
+
+    match remote {
+        Some(_) => sync(store),
+        None => Ok(Report::default()),
+    }
+}
+
+/**
+    Commits anything not committed yet, merges in what other machines
+    pushed and pushes the result.
+
+    The list stays locked throughout, so no command changes it between
+    reading it and saving the merged version. The merge is recorded in the
+    journal like any other change and can be undone.
+
+    The merged list is saved as soon as the merge is committed, before
+    pushing. Otherwise a push that fails would leave the repository ahead
+    of the list, and the next sync would commit the stale list on top of
+    the merge as if everything it brought in had been deleted.
+*/
+pub fn sync(store: &dyn Storage) -> Result<Report, Box<dyn Error>> {
+    let repo = Repo::for_list(store.path());
+    if !repo.exists() {
+        return Err("This list isn't synced yet: run todo sync init REMOTE".into());
+    }
+    if !repo.check(&["remote", "get-url", REMOTE])? {
+        return Err(format!("No remote to sync with: git -C {} remote add {} URL", repo.dir.display(), REMOTE).into());
+    }
+
+    let _lock = FileLock::acquire(store.path(), LOCK_TIMEOUT)?;
+    let local = store.load()?;
+    repo.commit(&local, "Save uncommitted changes")?;
+
+    let mut report = Report::default();
+    let mut saved = local.clone();
+    for attempt in 1..=PUSH_ATTEMPTS {
+        if let Some(theirs) = repo.fetch()?
+            && let Some(merged) = repo.merge(&theirs, &mut report)?
+        {
+            store.save(&merged)?;
+            record_change(store, "sync", &saved, &merged)?;
+            report.received = changed(&local, &merged);
+            saved = merged;
+        }
+
+        match repo.git(&["push", "-q", REMOTE, &format!("HEAD:{}", BRANCH)]) {
+            Ok(_) => break,
+            // Someone pushed since the fetch: merge that too.
+            Err(_) if attempt < PUSH_ATTEMPTS => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(report)
+}
+
+/// How many todos were added, removed or changed between two versions.
+fn changed(before: &[Todo], after: &[Todo]) -> usize {
+    let before: HashMap<&str, &Todo> = before.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+    let after_ids: HashMap<&str, &Todo> = after.iter().map(|todo| (todo.id.as_str(), todo)).collect();
+
+    let added_or_changed = after.iter().filter(|todo| before.get(todo.id.as_str()) != Some(todo)).count();
+    let removed = before.keys().filter(|id| !after_ids.contains_key(*id)).count();
+    added_or_changed + removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{update_with, JsonStore};
+
+    /// A bare repository standing in for the shared remote, and a store per
+    /// machine syncing through it.
+    fn setup(machines: usize) -> (tempfile::TempDir, Vec<JsonStore>) {
+        let dir = tempfile::tempdir().unwrap();
+        let remote = Repo { dir: dir.path().join("remote.git") };
+        fs::create_dir_all(&remote.dir).unwrap();
+        remote.git(&["init", "-q", "--bare", "-b", BRANCH]).unwrap();
+
+        let stores = (0..machines)
+            .map(|n| {
+                let machine = dir.path().join(format!("machine{}", n));
+                fs::create_dir_all(&machine).unwrap();
+                JsonStore::new(machine.join("todo.json"))
+            })
+            .collect();
+        (dir, stores)
+    }
+
+    fn remote(dir: &tempfile::TempDir) -> String {
+        dir.path().join("remote.git").display().to_string()
+    }
+
+    fn change(store: &JsonStore, description: &str, f: impl FnOnce(&mut Vec<Todo>)) {
+        update_with(store, LOCK_TIMEOUT, description, |todos| {
+            f(todos);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    fn texts(store: &JsonStore) -> Vec<String> {
+        store.load().unwrap().into_iter().map(|todo| todo.text).collect()
+    }
+
+    #[test]
+    fn concurrent_edits_merge_per_todo() {
+        let (dir, stores) = setup(2);
+        let (a, b) = (&stores[0], &stores[1]);
+
+        change(a, "add milk", |todos| todos.extend([Todo::new("milk"), Todo::new("bread")]));
+        init(a, Some(&remote(&dir))).unwrap();
+        let report = init(b, Some(&remote(&dir))).unwrap();
+        assert_eq!(report.received, 2);
+        assert_eq!(texts(b), vec!["milk", "bread"]);
+
+        change(a, "edit 1", |todos| todos[0].text = "oat milk".into());
+        change(b, "done 2", |todos| todos[1].complete());
+        change(b, "add eggs", |todos| todos.push(Todo::new("eggs")));
+
+        sync(a).unwrap();
+        let report = sync(b).unwrap();
+        assert_eq!(report.received, 1);
+        assert!(report.conflicts.is_empty());
+        sync(a).unwrap();
+
+        assert_eq!(a.load().unwrap(), b.load().unwrap());
+        assert_eq!(texts(a), vec!["oat milk", "bread", "eggs"]);
+        assert!(a.load().unwrap()[1].done);
+
+        // Every change was committed on the machine that made it.
+        let log = Repo::for_list(b.path()).git(&["log", "--format=%s"]).unwrap();
+        for message in ["Start syncing", "edit 1", "done 2", "add eggs", "Merge todos from origin"] {
+            assert!(log.lines().any(|line| line == message), "{} not in\n{}", message, log);
+        }
+    }
+
+    #[test]
+    fn conflicting_edits_converge() {
+        let (dir, stores) = setup(2);
+        let (a, b) = (&stores[0], &stores[1]);
+
+        change(a, "add", |todos| todos.push(Todo::new("milk")));
+        init(a, Some(&remote(&dir))).unwrap();
+        init(b, Some(&remote(&dir))).unwrap();
+
+        change(a, "edit", |todos| todos[0].text = "oat milk".into());
+        change(b, "edit", |todos| todos[0].text = "soy milk".into());
+
+        sync(a).unwrap();
+        let report = sync(b).unwrap();
+        assert_eq!(report.conflicts, vec![Conflict { text: "soy milk".into(), field: "text".into() }]);
+        sync(a).unwrap();
+
+        assert_eq!(texts(a), vec!["soy milk"]);
+        assert_eq!(texts(b), vec!["soy milk"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_failed_push_loses_nothing() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, stores) = setup(2);
+        let (a, b) = (&stores[0], &stores[1]);
+        init(a, Some(&remote(&dir))).unwrap();
+        init(b, Some(&remote(&dir))).unwrap();
+
+        // The remote turns pushes away while the marker file exists.
+        let marker = dir.path().join("reject");
+        let hook = dir.path().join("remote.git/hooks/pre-receive");
+        fs::write(&hook, format!("#!/bin/sh\ntest -e '{}' && exit 1\nexit 0\n", marker.display())).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+        change(b, "add", |todos| todos.push(Todo::new("b-new")));
+        sync(b).unwrap();
+        change(a, "add", |todos| todos.push(Todo::new("a-new")));
+        fs::write(&marker, "").unwrap();
+        assert!(sync(a).is_err());
+        assert_eq!(texts(a), vec!["a-new", "b-new"]);
+
+        fs::remove_file(&marker).unwrap();
+        sync(a).unwrap();
+        sync(b).unwrap();
+        assert_eq!(texts(a), vec!["a-new", "b-new"]);
+        assert_eq!(texts(b), vec!["a-new", "b-new"]);
+    }
+
+    #[test]
+    fn sync_needs_a_repository() {
+        let (_dir, stores) = setup(1);
+        assert!(sync(&stores[0]).is_err());
+
+        init(&stores[0], None).unwrap();
+        assert!(init(&stores[0], None).is_err());
+        assert!(sync(&stores[0]).unwrap_err().to_string().contains("No remote"));
+    }
+}
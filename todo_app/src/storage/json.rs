@@ -0,0 +1,341 @@
+use super::{corrupt, io_error, sibling, Backend, Storage, StorageError};
+use crate::id;
+use crate::todo::Todo;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How many previous versions of the file are kept as `todo.json.bak.N`.
+const BACKUPS: usize = 3;
+
+/**
+    Version of the on-disk layout written by `JsonStore::save`.
+
+    History:
+        1 - a bare JSON array of `{ "text", "done" }` objects
+        2 - `{ "version": 2, "todos": [...] }` with the rich `Todo` fields
+        3 - every todo has a stable `id`
+
+    Bump this whenever a change can't be expressed with `#[serde(default)]`
+    and add a step to `migrate` that upgrades the previous version.
+*/
+pub const SCHEMA_VERSION: u64 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct TodoFile {
+    version: u64,
+    todos: Vec<Todo>,
+}
+
+#[derive(Deserialize)]
+struct TodoV1 {
+    text: String,
+    done: bool,
+}
+
+impl TodoV1 {
+    /// Version 1 recorded no timestamps, so the file's modification time
+    /// stands in for them. It's the same on every load, which keeps the
+    /// derived IDs stable until the list is saved in the new format.
+    fn upgrade(self, modified: DateTime<Utc>) -> Todo {
+        let mut todo = Todo::new(&self.text);
+        todo.id.clear();
+        todo.created_at = modified;
+        if self.done {
+            todo.done = true;
+            todo.completed_at = Some(modified);
+        }
+        todo
+    }
+}
+
+/// The whole list as one pretty-printed JSON document.
+pub struct JsonStore {
+    path: PathBuf,
+}
+
+impl JsonStore {
+    pub fn new(path: impl Into<PathBuf>) -> JsonStore {
+        JsonStore { path: path.into() }
+    }
+}
+
+impl Storage for JsonStore {
+    fn backend(&self) -> Backend {
+        Backend::Json
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Vec<Todo>, StorageError> {
+        let path = &self.path;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let contents = fs::read_to_string(path).map_err(io_error(path))?;
+        let modified = fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+
+        let mut todos = migrate(path, &contents, modified)?;
+        id::fill_missing(&mut todos);
+        Ok(todos)
+    }
+
+    /**
+        Replaces the file without ever leaving a half-written one behind.
+
+        The new contents go to a temporary file next to the target, which is
+        flushed and fsynced before being renamed over the original. `rename`
+        within one directory is atomic, so a crash leaves either the old or the
+        new file. The directory is fsynced afterwards so the rename itself
+        survives a power loss. The previous file is rotated into the backups
+        first.
+    */
+    fn save(&self, todos: &[Todo]) -> Result<(), StorageError> {
+        let path = &self.path;
+        let tmp = sibling(path, ".tmp");
+        let contents = TodoFile { version: SCHEMA_VERSION, todos: todos.to_vec() };
+
+        let result = write_synced(&tmp, &contents);
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+            return result;
+        }
+
+        rotate_backups(path)?;
+        fs::rename(&tmp, path).map_err(io_error(path))?;
+        sync_parent(path)
+    }
+}
+
+fn write_synced(tmp: &Path, contents: &TodoFile) -> Result<(), StorageError> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(tmp)
+        .map_err(io_error(tmp))?;
+
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, contents)
+        .map_err(|e| io_error(tmp)(e.into()))?;
+    writer.write_all(b"\n").map_err(io_error(tmp))?;
+
+    let file = writer.into_inner().map_err(|e| io_error(tmp)(e.into_error()))?;
+    file.sync_all().map_err(io_error(tmp))
+}
+
+/// Shifts `todo.json.bak.1` to `.bak.2` and so on, then copies the current
+/// file to `.bak.1`. The oldest backup falls off the end.
+fn rotate_backups(path: &Path) -> Result<(), StorageError> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for n in (1..BACKUPS).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            let to = backup_path(path, n + 1);
+            fs::rename(&from, &to).map_err(io_error(&from))?;
+        }
+    }
+
+    let first = backup_path(path, 1);
+    fs::copy(path, &first).map_err(io_error(&first))?;
+    Ok(())
+}
+
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    sibling(path, &format!(".bak.{}", n))
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<(), StorageError> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    File::open(parent)
+        .and_then(|dir| dir.sync_all())
+        .map_err(io_error(parent))
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> Result<(), StorageError> {
+    Ok(())
+}
+
+/**
+    Upgrades any known layout to the current `Vec<Todo>`.
+
+    The text is parsed twice: once loosely to find the version, then again
+    straight into the matching struct. Deserializing from the text rather
+    than from the `Value` keeps line and column numbers in the errors.
+*/
+fn migrate(path: &Path, contents: &str, modified: DateTime<Utc>) -> Result<Vec<Todo>, StorageError> {
+    let unsupported = |message: String| StorageError::Unsupported { path: path.to_path_buf(), message };
+
+    let value: Value = serde_json::from_str(contents).map_err(|e| corrupt(path, e))?;
+    let version = match &value {
+        Value::Array(_) => 1,
+        Value::Object(map) => map
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| unsupported("Todo file is missing a schema version".to_string()))?,
+        _ => return Err(unsupported("Unrecognised todo file format".to_string())),
+    };
+
+    match version {
+        1 => {
+            let old: Vec<TodoV1> = serde_json::from_str(contents).map_err(|e| corrupt(path, e))?;
+            Ok(old.into_iter().map(|todo| todo.upgrade(modified)).collect())
+        }
+        // Version 2 only lacks IDs, which `load` fills in for every version.
+        2 | SCHEMA_VERSION => {
+            let file: TodoFile = serde_json::from_str(contents).map_err(|e| corrupt(path, e))?;
+            Ok(file.todos)
+        }
+        v => Err(unsupported(format!(
+            "Todo file has schema version {}, but this build only understands up to {}",
+            v, SCHEMA_VERSION
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::Priority;
+
+    fn migrate(value: Value) -> Result<Vec<Todo>, StorageError> {
+        super::migrate(Path::new("todo.json"), &value.to_string(), Utc::now())
+    }
+
+    #[test]
+    fn migrates_v1_array() {
+        let v1 = serde_json::json!([
+            { "text": "sample", "done": true },
+            { "text": "open", "done": false }
+        ]);
+
+        let todos = migrate(v1).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].text, "sample");
+        assert!(todos[0].done);
+        assert!(todos[0].completed_at.is_some());
+        assert!(!todos[1].done);
+        assert!(todos[1].completed_at.is_none());
+    }
+
+    #[test]
+    fn v1_ids_are_stable_across_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+        fs::write(store.path(), r#"[{ "text": "a", "done": false }, { "text": "b", "done": true }]"#).unwrap();
+
+        let first = store.load().unwrap();
+        assert_eq!(store.load().unwrap(), first);
+        assert!(first.iter().all(|todo| !todo.id.is_empty()));
+        assert_ne!(first[0].id, first[1].id);
+    }
+
+    #[test]
+    fn v2_todos_get_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+        let v2 = r#"{ "version": 2, "todos": [{ "text": "a", "done": false, "created_at": "2026-01-01T00:00:00Z" }] }"#;
+        fs::write(store.path(), v2).unwrap();
+
+        let todos = store.load().unwrap();
+        assert_eq!(todos[0].id.len(), 26);
+        assert_eq!(store.load().unwrap(), todos);
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let mut todo = Todo::new("write report");
+        todo.priority = Some(Priority::High);
+        todo.add_tag("work");
+        todo.project = Some("q4".to_string());
+        todo.notes = Some("ask for numbers".to_string());
+
+        let file = TodoFile { version: SCHEMA_VERSION, todos: vec![todo.clone()] };
+        let value = serde_json::to_value(&file).unwrap();
+
+        assert_eq!(migrate(value).unwrap(), vec![todo]);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let future = serde_json::json!({ "version": SCHEMA_VERSION + 1, "todos": [] });
+        assert!(migrate(future).is_err());
+    }
+
+    #[test]
+    fn rejects_objects_without_version() {
+        assert!(migrate(serde_json::json!({ "todos": [] })).is_err());
+    }
+
+    #[test]
+    fn save_then_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+
+        let todos = vec![Todo::new("one"), Todo::new("two")];
+        store.save(&todos).unwrap();
+
+        assert_eq!(store.load().unwrap(), todos);
+        assert!(!sibling(store.path(), ".tmp").exists());
+    }
+
+    #[test]
+    fn keeps_rolling_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+
+        for i in 0..=BACKUPS + 1 {
+            store.save(&[Todo::new(&i.to_string())]).unwrap();
+        }
+
+        // The newest backup holds the save before last, and only BACKUPS are kept.
+        let backup = |n| JsonStore::new(backup_path(store.path(), n)).load().unwrap();
+        assert_eq!(backup(1)[0].text, BACKUPS.to_string());
+        assert_eq!(backup(BACKUPS)[0].text, "1");
+        assert!(!backup_path(store.path(), BACKUPS + 1).exists());
+    }
+
+    #[test]
+    fn reports_corruption_with_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+        fs::write(store.path(), "{\n  \"version\": 2,\n  \"todos\": [ oops ]\n}").unwrap();
+
+        match store.load() {
+            Err(StorageError::Corrupt { line, column, .. }) => {
+                assert_eq!(line, 3);
+                assert_eq!(column, 14);
+            }
+            other => panic!("expected corruption error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_schema_errors_with_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonStore::new(dir.path().join("todo.json"));
+        fs::write(store.path(), "{\n  \"version\": 2,\n  \"todos\": [ { \"text\": 5 } ]\n}").unwrap();
+
+        match store.load() {
+            Err(StorageError::Corrupt { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected corruption error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use super::{io_error, sibling, StorageError};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The list used when no `--list` is given and there's no project list.
+pub const DEFAULT_LIST: &str = "default";
+
+/// File name, without extension, of a list that belongs to a directory tree.
+pub const PROJECT_LIST: &str = ".todo";
+
+/// Set once by `--list NAME`, before any command touches storage.
+static SELECTED: OnceLock<String> = OnceLock::new();
+
+/**
+    A todo list, as a path without extension: the JSON backend stores it in
+    `<base>.json` and the SQLite one in `<base>.db`.
+
+    Named lists live in the data directory (`$XDG_DATA_HOME/todo_app`, or
+    `~/.local/share/todo_app`). A project list is a `.todo.json` (or
+    `.todo.db`) in some directory, and is used from anywhere below it.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub name: Option<String>,
+    pub base: PathBuf,
+}
+
+impl List {
+    /// Whether the list has been saved in either backend.
+    pub fn exists(&self) -> bool {
+        self.file(".json").exists() || self.file(".db").exists()
+    }
+
+    /// A file that belongs to the list, such as `<list>.reminders.json`.
+    pub fn file(&self, suffix: &str) -> PathBuf {
+        sibling(&self.base, suffix)
+    }
+}
+
+/// Makes every later storage call use the named list instead of looking
+/// for one.
+pub fn select_list(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("Invalid list name: {} (use letters, digits, - and _)", name));
+    }
+    SELECTED.set(name.to_string()).map_err(|_| "Only one --list can be given".to_string())
+}
+
+/// The list commands work on: the one picked with `--list`, otherwise the
+/// nearest project list, otherwise the default list.
+pub fn current_list() -> Result<List, StorageError> {
+    let cwd = env::current_dir().map_err(io_error(Path::new(".")))?;
+    let data_dir = data_dir(env::var_os("XDG_DATA_HOME"), env::var_os("HOME"));
+    locate(SELECTED.get().map(String::as_str), &cwd, data_dir.as_deref())
+}
+
+fn locate(selected: Option<&str>, cwd: &Path, data_dir: Option<&Path>) -> Result<List, StorageError> {
+    if selected.is_none() {
+        let project = cwd
+            .ancestors()
+            .map(|dir| List { name: None, base: dir.join(PROJECT_LIST) })
+            .find(List::exists);
+        if let Some(project) = project {
+            return Ok(project);
+        }
+    }
+
+    let name = selected.unwrap_or(DEFAULT_LIST);
+    let data_dir = data_dir.ok_or(StorageError::NoDataDir)?;
+    Ok(List { name: Some(name.to_string()), base: data_dir.join(name) })
+}
+
+/// Where named lists are kept, per the XDG base directory spec, which only
+/// allows an absolute `XDG_DATA_HOME`.
+fn data_dir(xdg_data_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let base = xdg_data_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home.filter(|home| !home.is_empty()).map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(base.join("todo_app"))
+}
+
+/// The named lists saved in the data directory, sorted.
+pub fn named_lists() -> Result<Vec<String>, StorageError> {
+    let Some(dir) = data_dir(env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) else {
+        return Err(StorageError::NoDataDir);
+    };
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&dir).map_err(io_error(&dir))? {
+        let file_name = entry.map_err(io_error(&dir))?.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        // Backups, locks and journals have more than one dot, list names none.
+        let name = file_name.strip_suffix(".json").or_else(|| file_name.strip_suffix(".db"));
+        if let Some(name) = name.filter(|name| !name.contains('.'))
+            && !names.iter().any(|n| n == name)
+        {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_project_list_in_a_parent_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("src/deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.path().join(".todo.json"), "[]").unwrap();
+        let data = root.path().join("data");
+
+        let found = locate(None, &nested, Some(&data)).unwrap();
+        assert_eq!(found, List { name: None, base: root.path().join(PROJECT_LIST) });
+
+        // --list always means a named list, even inside a project.
+        let named = locate(Some("work"), &nested, Some(&data)).unwrap();
+        assert_eq!(named, List { name: Some("work".into()), base: data.join("work") });
+    }
+
+    #[test]
+    fn falls_back_to_the_default_list() {
+        let cwd = tempfile::tempdir().unwrap();
+        let data = cwd.path().join("data");
+
+        let list = locate(None, cwd.path(), Some(&data)).unwrap();
+        assert_eq!(list.base, data.join(DEFAULT_LIST));
+        assert!(!list.exists());
+        assert!(matches!(locate(None, cwd.path(), None), Err(StorageError::NoDataDir)));
+    }
+
+    #[test]
+    fn data_dir_follows_xdg() {
+        let xdg = data_dir(Some("/xdg".into()), Some("/home/me".into()));
+        assert_eq!(xdg, Some(PathBuf::from("/xdg/todo_app")));
+
+        // A relative XDG_DATA_HOME is invalid and ignored.
+        let home = data_dir(Some("relative".into()), Some("/home/me".into()));
+        assert_eq!(home, Some(PathBuf::from("/home/me/.local/share/todo_app")));
+
+        assert_eq!(data_dir(None, None), None);
+    }
+
+    #[test]
+    fn rejects_odd_list_names() {
+        for name in ["", "../etc", "a.b", "with space"] {
+            assert!(select_list(name).is_err(), "{}", name);
+        }
+    }
+}
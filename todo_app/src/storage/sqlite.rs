@@ -0,0 +1,178 @@
+use super::{Backend, Storage, StorageError};
+use crate::id;
+use crate::todo::Todo;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Bumped with every change to the tables below; stored in `PRAGMA user_version`.
+const SCHEMA_VERSION: i64 = 1;
+
+/**
+    The list in an embedded SQLite database.
+
+    Each todo is one row holding its JSON, keyed by its position in the list.
+    Keeping the item itself as JSON means new `Todo` fields need no table
+    migration, while `save` still only touches the rows that changed instead
+    of rewriting the whole list like `JsonStore` does.
+*/
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn new(path: impl Into<PathBuf>) -> SqliteStore {
+        SqliteStore { path: path.into() }
+    }
+
+    fn connect(&self) -> Result<Connection, StorageError> {
+        let conn = Connection::open(&self.path).map_err(self.db_error())?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(self.db_error())?;
+        conn.pragma_update(None, "journal_mode", "WAL").map_err(self.db_error())?;
+        conn.pragma_update(None, "synchronous", "FULL").map_err(self.db_error())?;
+        self.migrate(&conn)?;
+        Ok(conn)
+    }
+
+    fn migrate(&self, conn: &Connection) -> Result<(), StorageError> {
+        let version: i64 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(self.db_error())?;
+
+        if version > SCHEMA_VERSION {
+            return Err(StorageError::Unsupported {
+                path: self.path.clone(),
+                message: format!(
+                    "Database has schema version {}, but this build only understands up to {}",
+                    version, SCHEMA_VERSION
+                ),
+            });
+        }
+
+        if version < 1 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS todos (
+                    position INTEGER PRIMARY KEY,
+                    data     TEXT NOT NULL
+                );
+                PRAGMA user_version = 1;",
+            )
+            .map_err(self.db_error())?;
+        }
+
+        Ok(())
+    }
+
+    fn db_error(&self) -> impl FnOnce(rusqlite::Error) -> StorageError + '_ {
+        move |e| StorageError::Database { path: self.path.clone(), message: e.to_string() }
+    }
+}
+
+impl Storage for SqliteStore {
+    fn backend(&self) -> Backend {
+        Backend::Sqlite
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Vec<Todo>, StorageError> {
+        let conn = self.connect()?;
+        let mut stmt = conn
+            .prepare("SELECT position, data FROM todos ORDER BY position")
+            .map_err(self.db_error())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(self.db_error())?;
+
+        let mut todos = Vec::new();
+        for row in rows {
+            let (position, data) = row.map_err(self.db_error())?;
+            let todo = serde_json::from_str(&data).map_err(|e| StorageError::Database {
+                path: self.path.clone(),
+                message: format!("row {} is not a valid todo: {}", position, e),
+            })?;
+            todos.push(todo);
+        }
+
+        id::fill_missing(&mut todos);
+        Ok(todos)
+    }
+
+    /// Writes only the rows whose JSON differs from what's stored, all in
+    /// one transaction so readers never see a partial update.
+    fn save(&self, todos: &[Todo]) -> Result<(), StorageError> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction().map_err(self.db_error())?;
+
+        {
+            let mut current = tx
+                .prepare("SELECT data FROM todos WHERE position = ?1")
+                .map_err(self.db_error())?;
+            let mut upsert = tx
+                .prepare("INSERT OR REPLACE INTO todos (position, data) VALUES (?1, ?2)")
+                .map_err(self.db_error())?;
+
+            for (position, todo) in todos.iter().enumerate() {
+                let data = serde_json::to_string(todo).map_err(|e| StorageError::Database {
+                    path: self.path.clone(),
+                    message: e.to_string(),
+                })?;
+                let stored: Option<String> = current
+                    .query_row(params![position as i64], |row| row.get(0))
+                    .optional()
+                    .map_err(self.db_error())?;
+
+                if stored.as_deref() != Some(data.as_str()) {
+                    upsert.execute(params![position as i64, data]).map_err(self.db_error())?;
+                }
+            }
+
+            tx.execute("DELETE FROM todos WHERE position >= ?1", params![todos.len() as i64])
+                .map_err(self.db_error())?;
+        }
+
+        tx.commit().map_err(self.db_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_then_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(dir.path().join("todo.db"));
+
+        let mut todos = vec![Todo::new("one"), Todo::new("two"), Todo::new("three")];
+        store.save(&todos).unwrap();
+        assert_eq!(store.load().unwrap(), todos);
+
+        todos[1].complete();
+        todos.truncate(2);
+        store.save(&todos).unwrap();
+        assert_eq!(store.load().unwrap(), todos);
+    }
+
+    #[test]
+    fn empty_database_loads_empty_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(dir.path().join("todo.db"));
+
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_corrupt_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(dir.path().join("todo.db"));
+        store.save(&[Todo::new("fine")]).unwrap();
+
+        let conn = Connection::open(store.path()).unwrap();
+        conn.execute("UPDATE todos SET data = '{' WHERE position = 0", []).unwrap();
+
+        assert!(matches!(store.load(), Err(StorageError::Database { .. })));
+    }
+}
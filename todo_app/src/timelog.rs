@@ -0,0 +1,197 @@
+/**
+    Time tracking.
+
+    `todo start` opens a session on a todo and `todo stop` closes it. Only
+    one session can be running in the whole list, so no minute is ever
+    counted against two todos. Completing a todo stops its session.
+*/
+use crate::todo::Todo;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    pub start: DateTime<Utc>,
+    /// Unset while the session is running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    /// How much of the session falls between `from` and `to`, counting a
+    /// running one up to `now`.
+    pub fn within(&self, from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> TimeDelta {
+        let start = self.start.max(from);
+        let end = self.end.unwrap_or(now).min(to);
+        (end - start).max(TimeDelta::zero())
+    }
+}
+
+/// The todo with the running session, if any.
+pub fn running(todos: &[Todo]) -> Option<usize> {
+    todos.iter().position(|todo| todo.sessions.iter().any(|s| s.end.is_none()))
+}
+
+pub fn start(todos: &mut [Todo], i: usize, now: DateTime<Utc>) -> Result<(), String> {
+    if let Some(r) = running(todos) {
+        let since = todos[r].sessions.iter().find(|s| s.end.is_none()).map(|s| s.start).unwrap_or(now);
+        let since = since.with_timezone(&Local).format("%H:%M");
+        return Err(if r == i {
+            format!("Already tracking \"{}\" since {}", todos[r].text, since)
+        } else {
+            format!("Already tracking \"{}\" since {}; run todo stop first", todos[r].text, since)
+        });
+    }
+
+    todos[i].sessions.push(Session { start: now, end: None });
+    Ok(())
+}
+
+/// Closes the running session. Returns its todo and how long it ran.
+pub fn stop(todos: &mut [Todo], now: DateTime<Utc>) -> Option<(usize, TimeDelta)> {
+    let i = running(todos)?;
+    let session = todos[i].sessions.iter_mut().find(|s| s.end.is_none())?;
+    session.end = Some(now.max(session.start));
+    Some((i, now - session.start))
+}
+
+/// All the time tracked on a todo.
+pub fn total(todo: &Todo, now: DateTime<Utc>) -> TimeDelta {
+    todo.sessions.iter().map(|s| s.end.unwrap_or(now) - s.start).sum()
+}
+
+/// The Monday starting the week `day` is in.
+pub fn week_start(day: NaiveDate) -> NaiveDate {
+    day - Days::new(day.weekday().num_days_from_monday().into())
+}
+
+/// Midnight at the start of `day`, local time.
+pub fn local_midnight(day: NaiveDate) -> DateTime<Utc> {
+    let midnight = day.and_time(NaiveTime::MIN);
+    match midnight.and_local_timezone(Local).earliest() {
+        Some(t) => t.with_timezone(&Utc),
+        // Midnight skipped by a DST change; close enough for a report.
+        None => midnight.and_utc(),
+    }
+}
+
+/// Time spent between two instants, broken down a few ways. Each list is
+/// longest first.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub total: TimeDelta,
+    pub tasks: Vec<(String, TimeDelta)>,
+    pub tags: Vec<(String, TimeDelta)>,
+    pub projects: Vec<(String, TimeDelta)>,
+}
+
+/// Todos without tags or a project are counted under "(none)", so the
+/// projects add up to the total. A todo with several tags counts in each.
+pub fn report(todos: &[Todo], from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> Report {
+    let mut total = TimeDelta::zero();
+    let mut tasks = Vec::new();
+    let mut tags: HashMap<String, TimeDelta> = HashMap::new();
+    let mut projects: HashMap<String, TimeDelta> = HashMap::new();
+
+    for todo in todos {
+        let spent: TimeDelta = todo.sessions.iter().map(|s| s.within(from, to, now)).sum();
+        if spent.is_zero() {
+            continue;
+        }
+
+        total += spent;
+        tasks.push((todo.text.clone(), spent));
+        let none = vec!["(none)".to_string()];
+        for tag in if todo.tags.is_empty() { &none } else { &todo.tags } {
+            *tags.entry(tag.clone()).or_default() += spent;
+        }
+        *projects.entry(todo.project.clone().unwrap_or_else(|| "(none)".to_string())).or_default() += spent;
+    }
+
+    Report { total, tasks: longest_first(tasks), tags: longest_first(tags), projects: longest_first(projects) }
+}
+
+fn longest_first(times: impl IntoIterator<Item = (String, TimeDelta)>) -> Vec<(String, TimeDelta)> {
+    let mut times: Vec<_> = times.into_iter().collect();
+    times.sort_by(|(a, a_time), (b, b_time)| b_time.cmp(a_time).then_with(|| a.cmp(b)));
+    times
+}
+
+/// `2h 05m`, or just `12m` under an hour.
+pub fn format_duration(d: TimeDelta) -> String {
+    let minutes = d.num_minutes();
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn only_one_timer_runs_at_a_time() {
+        let mut todos = vec![Todo::new("write"), Todo::new("review")];
+        start(&mut todos, 0, at("2026-10-19T09:00:00Z")).unwrap();
+
+        assert!(start(&mut todos, 1, at("2026-10-19T09:10:00Z")).unwrap_err().contains("todo stop"));
+        assert!(start(&mut todos, 0, at("2026-10-19T09:10:00Z")).is_err());
+
+        assert_eq!(stop(&mut todos, at("2026-10-19T09:45:00Z")), Some((0, TimeDelta::minutes(45))));
+        assert_eq!(stop(&mut todos, at("2026-10-19T09:50:00Z")), None);
+        start(&mut todos, 1, at("2026-10-19T10:00:00Z")).unwrap();
+        assert_eq!(running(&todos), Some(1));
+        assert_eq!(total(&todos[1], at("2026-10-19T10:30:00Z")), TimeDelta::minutes(30));
+    }
+
+    #[test]
+    fn completing_a_todo_stops_its_timer() {
+        let mut todos = vec![Todo::new("write")];
+        start(&mut todos, 0, Utc::now()).unwrap();
+        todos[0].complete();
+        assert_eq!(running(&todos), None);
+    }
+
+    #[test]
+    fn report_clips_sessions_to_the_week() {
+        let session = |start: &str, end: Option<&str>| Session { start: at(start), end: end.map(at) };
+        let mut write = Todo::new("write");
+        write.project = Some("book".into());
+        write.tags = vec!["deep".into(), "solo".into()];
+        write.sessions = vec![
+            session("2026-10-11T23:00:00Z", Some("2026-10-12T01:00:00Z")),
+            session("2026-10-13T09:00:00Z", Some("2026-10-13T10:30:00Z")),
+        ];
+        let mut review = Todo::new("review");
+        review.sessions = vec![session("2026-10-14T09:00:00Z", None)];
+        let idle = Todo::new("idle");
+
+        let (from, to) = (at("2026-10-12T00:00:00Z"), at("2026-10-19T00:00:00Z"));
+        let report = report(&[write, review, idle], from, to, at("2026-10-14T09:20:00Z"));
+
+        assert_eq!(report.total, TimeDelta::minutes(170));
+        assert_eq!(
+            report.tasks,
+            vec![("write".to_string(), TimeDelta::minutes(150)), ("review".to_string(), TimeDelta::minutes(20))]
+        );
+        let tags: Vec<&str> = report.tags.iter().map(|(tag, _)| tag.as_str()).collect();
+        assert_eq!(tags, vec!["deep", "solo", "(none)"]);
+        assert_eq!(report.projects[0], ("book".to_string(), TimeDelta::minutes(150)));
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        let day = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert_eq!(week_start(day("2026-10-18")), day("2026-10-12"));
+        assert_eq!(week_start(day("2026-10-19")), day("2026-10-19"));
+        assert_eq!(format_duration(TimeDelta::minutes(125)), "2h 05m");
+        assert_eq!(format_duration(TimeDelta::seconds(59)), "0m");
+    }
+}
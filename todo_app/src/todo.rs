@@ -0,0 +1,155 @@
+use crate::id::new_id;
+use crate::nlp;
+use crate::recur::Recurrence;
+use crate::timelog::Session;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Priority, String> {
+        match s.to_lowercase().as_str() {
+            "low" | "l" => Ok(Priority::Low),
+            "medium" | "med" | "m" => Ok(Priority::Medium),
+            "high" | "h" => Ok(Priority::High),
+            _ => Err(format!("Unknown priority: {} (expected low, medium or high)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/**
+    A single todo item.
+
+    Everything except `text` and `done` was added after the first release, so
+    every new field is optional (or defaults to empty) and is skipped when
+    unset. A missing `id` is filled in by `id::fill_missing` on load. That
+    keeps `todo.json` readable and lets older entries deserialize without
+    any special casing.
+
+    `parent` and `blocked_by` refer to other todos by ID; see `tree`.
+    `sessions` is the time worked on it; see `timelog`.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Todo {
+    #[serde(default)]
+    pub id: String,
+    pub text: String,
+    pub done: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDateTime>,
+    /// Local time `todo daemon` should send a reminder at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remind_at: Option<NaiveDateTime>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recur: Option<Recurrence>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_by: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sessions: Vec<Session>,
+}
+
+impl Todo {
+    pub fn new(text: &str) -> Todo {
+        Todo {
+            id: new_id(),
+            text: text.to_string(),
+            done: false,
+            priority: None,
+            due: None,
+            remind_at: None,
+            created_at: Utc::now(),
+            completed_at: None,
+            tags: Vec::new(),
+            project: None,
+            notes: None,
+            recur: None,
+            parent: None,
+            blocked_by: Vec::new(),
+            sessions: Vec::new(),
+        }
+    }
+
+    /// Marks the todo done, stopping its timer if it's running.
+    pub fn complete(&mut self) {
+        let now = Utc::now();
+        self.done = true;
+        self.completed_at = Some(now);
+        if let Some(session) = self.sessions.iter_mut().find(|s| s.end.is_none()) {
+            session.end = Some(now.max(session.start));
+        }
+    }
+
+    pub fn reopen(&mut self) {
+        self.done = false;
+        self.completed_at = None;
+    }
+
+    pub fn add_tag(&mut self, tag: &str) {
+        let tag = tag.trim_start_matches('#').to_lowercase();
+        if !tag.is_empty() && !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+    }
+}
+
+/// Parses `YYYY-MM-DD` or `YYYY-MM-DD HH:MM`, or a phrase like "tomorrow"
+/// or "next friday 9am" (see `nlp::parse_date`). A bare date means midnight.
+pub fn parse_due(s: &str) -> Result<NaiveDateTime, String> {
+    let s = s.trim();
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        return Ok(dt);
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M") {
+        return Ok(dt);
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(d.and_time(NaiveTime::MIN));
+    }
+
+    let words: Vec<&str> = s.split_whitespace().collect();
+    nlp::parse_date(&words, Local::now().naive_local())
+        .ok_or_else(|| format!("Invalid due date: {} (expected YYYY-MM-DD [HH:MM] or e.g. tomorrow)", s))
+}
+
+pub fn format_due(due: &NaiveDateTime) -> String {
+    if due.time() == NaiveTime::MIN {
+        due.format("%Y-%m-%d").to_string()
+    } else {
+        due.format("%Y-%m-%d %H:%M").to_string()
+    }
+}
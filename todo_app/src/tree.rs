@@ -0,0 +1,297 @@
+/**
+    Subtasks and dependencies.
+
+    A todo can sit under a parent and can be blocked by other todos. Both
+    links are stored as IDs, so they survive reordering, and both are kept
+    free of cycles: nothing can be its own ancestor, and nothing can end up
+    waiting on itself. Lists edited by hand can still contain cycles or links
+    to todos that no longer exist, so every walk here guards against both.
+*/
+use crate::todo::Todo;
+use std::collections::HashSet;
+
+fn index_of(todos: &[Todo], id: &str) -> Option<usize> {
+    todos.iter().position(|todo| todo.id == id)
+}
+
+pub fn parent(todos: &[Todo], i: usize) -> Option<usize> {
+    index_of(todos, todos[i].parent.as_deref()?)
+}
+
+pub fn children(todos: &[Todo], i: usize) -> Vec<usize> {
+    let id = todos[i].id.as_str();
+    (0..todos.len()).filter(|&c| todos[c].parent.as_deref() == Some(id)).collect()
+}
+
+/// Every todo below `i`, depth first.
+pub fn descendants(todos: &[Todo], i: usize) -> Vec<usize> {
+    let mut seen = HashSet::from([i]);
+    let mut found = Vec::new();
+    let mut stack = children(todos, i);
+    stack.reverse();
+
+    while let Some(c) = stack.pop() {
+        if !seen.insert(c) {
+            continue;
+        }
+        found.push(c);
+        stack.extend(children(todos, c).into_iter().rev());
+    }
+    found
+}
+
+/// How many of the todos below `i` are done, out of how many, if it has any.
+pub fn progress(todos: &[Todo], i: usize) -> Option<(usize, usize)> {
+    let below = descendants(todos, i);
+    if below.is_empty() {
+        return None;
+    }
+    let done = below.iter().filter(|&&d| todos[d].done).count();
+    Some((done, below.len()))
+}
+
+/// The todos `i` is still waiting on.
+pub fn open_blockers(todos: &[Todo], i: usize) -> Vec<usize> {
+    todos[i]
+        .blocked_by
+        .iter()
+        .filter_map(|id| index_of(todos, id))
+        .filter(|&b| !todos[b].done)
+        .collect()
+}
+
+pub fn set_parent(todos: &mut [Todo], i: usize, parent: Option<usize>) -> Result<(), String> {
+    if let Some(p) = parent
+        && (p == i || descendants(todos, i).contains(&p))
+    {
+        return Err(format!("\"{}\" can't go under itself or its own subtask \"{}\"", todos[i].text, todos[p].text));
+    }
+    todos[i].parent = parent.map(|p| todos[p].id.clone());
+    Ok(())
+}
+
+/// Makes `i` wait on `blocker`, unless `blocker` already waits on `i`,
+/// directly or through others.
+pub fn block(todos: &mut [Todo], i: usize, blocker: usize) -> Result<(), String> {
+    if let Some(path) = waiting_path(todos, blocker, i) {
+        let cycle: Vec<String> = [i].iter().chain(path.iter().rev()).map(|&t| format!("\"{}\"", todos[t].text)).collect();
+        return Err(format!("That would make a dependency cycle: {}", cycle.join(" waits on ")));
+    }
+
+    let id = todos[blocker].id.clone();
+    if !todos[i].blocked_by.contains(&id) {
+        todos[i].blocked_by.push(id);
+    }
+    Ok(())
+}
+
+/// Returns whether `i` was blocked by `blocker`.
+pub fn unblock(todos: &mut [Todo], i: usize, blocker: usize) -> bool {
+    let id = todos[blocker].id.clone();
+    let before = todos[i].blocked_by.len();
+    todos[i].blocked_by.retain(|b| *b != id);
+    todos[i].blocked_by.len() != before
+}
+
+/// The chain of todos from `to` back to `from` if `from` waits on `to`
+/// (or is `to`), following `blocked_by` links.
+fn waiting_path(todos: &[Todo], from: usize, to: usize) -> Option<Vec<usize>> {
+    let mut came_from = vec![None; todos.len()];
+    let mut seen = HashSet::from([from]);
+    let mut stack = vec![from];
+
+    while let Some(t) = stack.pop() {
+        if t == to {
+            let mut path = vec![t];
+            while let Some(prev) = came_from[*path.last().unwrap()] {
+                path.push(prev);
+            }
+            return Some(path);
+        }
+        for id in &todos[t].blocked_by {
+            if let Some(next) = index_of(todos, id)
+                && seen.insert(next)
+            {
+                came_from[next] = Some(t);
+                stack.push(next);
+            }
+        }
+    }
+    None
+}
+
+/**
+    Cleans up links to todos that were just removed: their subtasks move up
+    to the removed todo's own parent, and nothing stays blocked by them.
+*/
+pub fn forget(todos: &mut [Todo], removed: &[Todo]) {
+    let parent_of = |id: &str| removed.iter().find(|r| r.id == id).map(|r| r.parent.clone());
+
+    for todo in todos.iter_mut() {
+        // Bounded, in case the removed todos' parents form a cycle.
+        for _ in 0..=removed.len() {
+            match todo.parent.as_deref().and_then(parent_of) {
+                Some(grandparent) => todo.parent = grandparent,
+                None => break,
+            }
+        }
+        todo.blocked_by.retain(|id| removed.iter().all(|r| r.id != *id));
+    }
+}
+
+/**
+    Orders `items` (indices into `todos`) as a tree and returns each with the
+    lines to draw before it. Subtasks follow their parent in the order they
+    appear in `items`; one whose parent isn't in `items` is shown at the top
+    level.
+*/
+pub fn layout(todos: &[Todo], items: &[usize]) -> Vec<(usize, String)> {
+    let mut layout = Layout {
+        todos,
+        items,
+        shown: items.iter().copied().collect(),
+        placed: HashSet::new(),
+        lines: Vec::with_capacity(items.len()),
+    };
+
+    for &i in items {
+        if layout.shown_parent(i).is_none() {
+            layout.place(i, String::new(), String::new());
+        }
+    }
+    // Anything left sits in a parent cycle; show it rather than lose it.
+    for &i in items {
+        layout.place(i, String::new(), String::new());
+    }
+    layout.lines
+}
+
+struct Layout<'a> {
+    todos: &'a [Todo],
+    items: &'a [usize],
+    shown: HashSet<usize>,
+    placed: HashSet<usize>,
+    lines: Vec<(usize, String)>,
+}
+
+impl Layout<'_> {
+    fn shown_parent(&self, i: usize) -> Option<usize> {
+        parent(self.todos, i).filter(|p| *p != i && self.shown.contains(p))
+    }
+
+    /// Adds `i` after `prefix`, then its subtasks indented by `indent`.
+    fn place(&mut self, i: usize, prefix: String, indent: String) {
+        if !self.placed.insert(i) {
+            return;
+        }
+        self.lines.push((i, prefix));
+
+        let kids: Vec<usize> = self
+            .items
+            .iter()
+            .copied()
+            .filter(|&c| self.shown_parent(c) == Some(i) && !self.placed.contains(&c))
+            .collect();
+        for (n, &kid) in kids.iter().enumerate() {
+            let last = n + 1 == kids.len();
+            let branch = format!("{}{}", indent, if last { "└─ " } else { "├─ " });
+            let below = format!("{}{}", indent, if last { "   " } else { "│  " });
+            self.place(kid, branch, below);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(texts: &[&str]) -> Vec<Todo> {
+        texts.iter().map(|text| Todo::new(text)).collect()
+    }
+
+    #[test]
+    fn progress_rolls_up_from_all_levels() {
+        let mut todos = list(&["release", "docs", "tests", "unit", "e2e"]);
+        set_parent(&mut todos, 1, Some(0)).unwrap();
+        set_parent(&mut todos, 2, Some(0)).unwrap();
+        set_parent(&mut todos, 3, Some(2)).unwrap();
+        set_parent(&mut todos, 4, Some(2)).unwrap();
+        todos[3].complete();
+
+        assert_eq!(descendants(&todos, 0), vec![1, 2, 3, 4]);
+        assert_eq!(progress(&todos, 0), Some((1, 4)));
+        assert_eq!(progress(&todos, 2), Some((1, 2)));
+        assert_eq!(progress(&todos, 1), None);
+    }
+
+    #[test]
+    fn refuses_parent_cycles() {
+        let mut todos = list(&["a", "b", "c"]);
+        set_parent(&mut todos, 1, Some(0)).unwrap();
+        set_parent(&mut todos, 2, Some(1)).unwrap();
+
+        assert!(set_parent(&mut todos, 0, Some(2)).is_err());
+        assert!(set_parent(&mut todos, 0, Some(0)).is_err());
+        assert_eq!(todos[0].parent, None);
+        set_parent(&mut todos, 2, None).unwrap();
+        set_parent(&mut todos, 0, Some(2)).unwrap();
+    }
+
+    #[test]
+    fn refuses_dependency_cycles() {
+        let mut todos = list(&["deploy", "build", "test"]);
+        block(&mut todos, 0, 1).unwrap();
+        block(&mut todos, 1, 2).unwrap();
+
+        let err = block(&mut todos, 2, 0).unwrap_err();
+        assert!(err.ends_with("\"test\" waits on \"deploy\" waits on \"build\" waits on \"test\""), "{}", err);
+        assert!(block(&mut todos, 2, 2).is_err());
+
+        assert_eq!(open_blockers(&todos, 0), vec![1]);
+        todos[1].complete();
+        assert!(open_blockers(&todos, 0).is_empty());
+
+        assert!(unblock(&mut todos, 0, 1));
+        assert!(!unblock(&mut todos, 0, 1));
+    }
+
+    #[test]
+    fn forget_moves_subtasks_up() {
+        let mut todos = list(&["top", "middle", "bottom", "waiting"]);
+        set_parent(&mut todos, 1, Some(0)).unwrap();
+        set_parent(&mut todos, 2, Some(1)).unwrap();
+        block(&mut todos, 3, 1).unwrap();
+
+        let removed = vec![todos.remove(1)];
+        forget(&mut todos, &removed);
+
+        assert_eq!(todos[1].parent.as_deref(), Some(todos[0].id.as_str()));
+        assert!(todos[2].blocked_by.is_empty());
+    }
+
+    #[test]
+    fn layout_draws_a_tree() {
+        let mut todos = list(&["a", "a1", "b", "a2", "a1x"]);
+        set_parent(&mut todos, 1, Some(0)).unwrap();
+        set_parent(&mut todos, 3, Some(0)).unwrap();
+        set_parent(&mut todos, 4, Some(1)).unwrap();
+
+        let lines = layout(&todos, &[0, 1, 2, 3, 4]);
+        let drawn: Vec<String> = lines.iter().map(|(i, prefix)| format!("{}{}", prefix, todos[*i].text)).collect();
+        assert_eq!(drawn, vec!["a", "├─ a1", "│  └─ a1x", "└─ a2", "b"]);
+
+        // A subtask whose parent is filtered out moves to the top level.
+        let lines = layout(&todos, &[4, 2]);
+        assert_eq!(lines, vec![(4, String::new()), (2, String::new())]);
+    }
+
+    #[test]
+    fn layout_survives_hand_made_cycles() {
+        let mut todos = list(&["a", "b"]);
+        todos[0].parent = Some(todos[1].id.clone());
+        todos[1].parent = Some(todos[0].id.clone());
+
+        assert_eq!(layout(&todos, &[0, 1]).len(), 2);
+        assert_eq!(descendants(&todos, 0), vec![1]);
+    }
+}